@@ -0,0 +1,82 @@
+use std::f64;
+
+use crate::{Point3, Ray};
+
+/// An axis aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    /// A box containing nothing, which grows to fit whatever is added to it
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::from(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point3::from(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        }
+    }
+
+    /// Grow the box to contain the given point
+    pub fn add_point(&mut self, p: Point3) {
+        self.min = Point3::from(
+            self.min.x().min(p.x()),
+            self.min.y().min(p.y()),
+            self.min.z().min(p.z()),
+        );
+        self.max = Point3::from(
+            self.max.x().max(p.x()),
+            self.max.y().max(p.y()),
+            self.max.z().max(p.z()),
+        );
+    }
+
+    /// The smallest box containing both boxes
+    pub fn surrounding(&self, other: &Aabb) -> Self {
+        let mut result = *self;
+        result.add_point(other.min);
+        result.add_point(other.max);
+
+        result
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// The axis the box is longest along, 0 being X
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+
+        if size.x() > size.y() && size.x() > size.z() {
+            0
+        } else if size.y() > size.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Check whether the ray passes through the box between `t_min` and `t_max`
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction()[axis];
+            let mut t0 = (self.min[axis] - ray.origin()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin()[axis]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
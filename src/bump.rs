@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::material::{Material, ScatterRecord};
+use crate::medium::Medium;
+use crate::texture::Texture;
+use crate::{Color, HitRecord, Ray};
+
+/// Step used to find the slope of a bump map, both in texture coordinates and
+/// along the surface
+const BUMP_DELTA: f64 = 1e-3;
+
+/// Where the fine detail of a surface comes from
+pub enum Detail {
+    /// Normals in the tangent frame of the surface, with each component
+    /// mapped from [-1, 1] to [0, 1] as normal map images store them
+    NormalMap(Arc<dyn Texture>),
+    /// Heights above the surface, scaled by `strength`. Only the slope
+    /// matters, so the texture's average is used for colored ones.
+    BumpMap {
+        height: Arc<dyn Texture>,
+        strength: f64,
+    },
+}
+
+/// Another material with its shading normal tilted by a normal or bump map,
+/// which fakes detail too small to be worth modelling.
+///
+/// The geometry is left alone, so silhouettes and shadows stay smooth.
+pub struct Perturbed {
+    material: Arc<dyn Material>,
+    detail: Detail,
+}
+
+impl Perturbed {
+    pub fn from(material: Arc<dyn Material>, detail: Detail) -> Self {
+        Self { material, detail }
+    }
+
+    /// The hit record with the normal swapped for the perturbed one
+    fn perturb(&self, ray_in: &Ray, rec: &HitRecord) -> HitRecord {
+        let t = rec.tangent;
+        let b = rec.bitangent;
+
+        // The frame follows the texture rather than the side that was hit, so
+        // work with its own normal and turn the result around to match
+        let n = t.cross(b);
+        let side = if n.dot(rec.normal) < 0.0 { -1.0 } else { 1.0 };
+
+        let normal = match &self.detail {
+            Detail::NormalMap(map) => {
+                let m = 2.0 * map.value(rec.u, rec.v, rec.p) - Color::from(1.0, 1.0, 1.0);
+                m.x() * t + m.y() * b + m.z() * n
+            }
+            Detail::BumpMap { height, strength } => {
+                let h = |du: f64, dv: f64| {
+                    let p = rec.p + du * t + dv * b;
+                    let c = height.value(rec.u + du, rec.v + dv, p);
+                    (c.x() + c.y() + c.z()) / 3.0
+                };
+                let h0 = h(0.0, 0.0);
+                let slope_u = (h(BUMP_DELTA, 0.0) - h0) / BUMP_DELTA;
+                let slope_v = (h(0.0, BUMP_DELTA) - h0) / BUMP_DELTA;
+
+                n - *strength * (slope_u * t + slope_v * b)
+            }
+        };
+
+        let mut perturbed = rec.clone();
+        let normal = side * normal.unit_vector();
+
+        // A normal tilted away from the viewer would leave nothing to see,
+        // so keep the real one there
+        if normal.dot(ray_in.direction()) < 0.0 {
+            perturbed.normal = normal;
+        }
+
+        perturbed
+    }
+}
+
+impl Material for Perturbed {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.material
+            .scatter(ray_in, &self.perturb(ray_in, rec), srec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.material
+            .eval(ray_in, &self.perturb(ray_in, rec), scattered)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material
+            .pdf(ray_in, &self.perturb(ray_in, rec), scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(ray_in, &self.perturb(ray_in, rec))
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.material.medium()
+    }
+
+    fn roughness(&self) -> f64 {
+        self.material.roughness()
+    }
+
+    fn ior(&self) -> Option<f64> {
+        self.material.ior()
+    }
+
+    fn priority(&self) -> u32 {
+        self.material.priority()
+    }
+}
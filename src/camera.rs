@@ -1,6 +1,21 @@
 use crate::ray::*;
 use crate::vec3::*;
 
+/// Where light leaving a point reaches the camera, picked by
+/// `Camera::sample_towards`
+pub struct CameraSample {
+    /// The point on the lens the light arrives at
+    pub lens: Point3,
+    /// Where the light lands on the image, both from 0 to 1 from the bottom
+    /// left like `get_ray` takes them
+    pub s: f64,
+    pub t: f64,
+    /// How much the camera responds to the light, per unit of image area,
+    /// over the density of picking the point on the lens as seen from the
+    /// point the light leaves
+    pub importance: f64,
+}
+
 pub struct Camera {
     origin: Point3,
     lower_left_corner: Point3,
@@ -10,6 +25,8 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    /// Area of the image on a plane one unit in front of the lens
+    image_area: f64,
 }
 
 impl Camera {
@@ -27,8 +44,6 @@ impl Camera {
         let vp_height = 2.0 * h;
         let vp_width = aspect_ratio * vp_height;
 
-        let focal_length = 1.0;
-
         let w = (look_from - look_at).unit_vector();
         let u = vup.cross(w).unit_vector();
         let v = w.cross(u);
@@ -45,10 +60,11 @@ impl Camera {
             horizontal,
             vertical,
             lower_left_corner,
-            w,
             u,
             v,
+            w,
             lens_radius,
+            image_area: vp_width * vp_height,
         }
     }
 
@@ -84,4 +100,44 @@ impl Camera {
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
     }
+
+    /// Pick a point on the lens for light leaving `p` to arrive at, and
+    /// work out where on the image it lands. Returns `None` for points
+    /// behind the camera.
+    pub fn sample_towards(&self, p: Point3) -> Option<CameraSample> {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        let lens = self.origin + self.u * rd.x() + self.v * rd.y();
+
+        let to_point = p - lens;
+        let distance = to_point.length();
+        let direction = to_point / distance;
+
+        let cos_theta = -direction.dot(self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Every ray through a point on the image passes through the same
+        // point on the plane in focus, whichever part of the lens it left
+        let focus = (self.lower_left_corner - lens).dot(self.w) / direction.dot(self.w);
+        let on_plane = lens + focus * direction - self.lower_left_corner;
+
+        Some(CameraSample {
+            lens,
+            s: on_plane.dot(self.horizontal) / self.horizontal.length_squared(),
+            t: on_plane.dot(self.vertical) / self.vertical.length_squared(),
+            importance: 1.0 / (self.image_area * cos_theta.powi(3) * distance * distance),
+        })
+    }
+
+    /// The density of `get_ray` picking a ray in the direction, with respect
+    /// to solid angle, when picking points on the image evenly
+    pub fn pdf_direction(&self, direction: Vec3) -> f64 {
+        let cos_theta = -direction.unit_vector().dot(self.w);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        1.0 / (self.image_area * cos_theta.powi(3))
+    }
 }
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::material::{local_dirs, Material, ScatterRecord};
+use crate::medium::Medium;
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::sampler;
+use crate::{Color, HitRecord, Ray, Vec3};
+
+/// A dielectric coating over another material, like clear coat on car paint
+/// or varnish on wood.
+///
+/// Light is either reflected off the top of the coat by its Fresnel term, or
+/// goes through it, is scattered by the base, and comes back out. Whatever
+/// isn't reflected on the way in or out reaches the base or the eye, tinted
+/// by absorption in the coat over the distance it travels through it. Light
+/// reflected back down off the underside of the coat isn't followed, so each
+/// part can be evaluated on its own for direct lighting.
+///
+/// The base should be opaque, since light passing through it is lost.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ref_idx: f64,
+    distribution: TrowbridgeReitz,
+    medium: Option<Medium>,
+    thickness: f64,
+}
+
+impl Coated {
+    pub fn from(base: Arc<dyn Material>, ref_idx: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            ref_idx,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            medium: None,
+            thickness: 0.0,
+        }
+    }
+
+    /// Make the coat `thickness` deep and filled with an absorbing medium
+    pub fn with_medium(mut self, medium: Medium, thickness: f64) -> Self {
+        self.medium = Some(medium);
+        self.thickness = thickness;
+        self
+    }
+
+    /// Fraction of light going through the top of the coat in direction `w`,
+    /// which points away from the surface, that isn't reflected there or
+    /// absorbed on its way between the top and the base
+    fn transmitted(&self, w: Vec3) -> Color {
+        let fresnel = microfacet::fresnel_dielectric(w.z(), self.ref_idx);
+
+        // Light bends towards the normal going in, so it travels through the
+        // coat at a steeper angle than it arrives at
+        let sin2 = (1.0 - w.z() * w.z()) / (self.ref_idx * self.ref_idx);
+        let cos = (1.0 - sin2).max(0.0).sqrt();
+
+        let absorbed = match self.medium {
+            Some(medium) => medium.transmittance(self.thickness / cos),
+            None => Color::from(1.0, 1.0, 1.0),
+        };
+
+        (1.0 - fresnel) * absorbed
+    }
+
+    /// Chance of sampling a reflection off the coat rather than the base
+    fn coat_prob(&self, wo: Vec3) -> f64 {
+        microfacet::fresnel_dielectric(wo.z(), self.ref_idx)
+    }
+
+    /// Light reflected off a rough coat, times the cosine of `wi`. A smooth
+    /// coat only reflects in one direction, so it gives nothing here.
+    fn coat_eval(&self, distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3) -> f64 {
+        if distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        distribution.reflection(wo, wi, wm)
+            * microfacet::fresnel_dielectric(wo.dot(wm), self.ref_idx)
+    }
+
+    fn coat_pdf(&self, distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3) -> f64 {
+        if distribution.is_smooth() {
+            return 0.0;
+        }
+
+        distribution.reflection_pdf(wo, (wo + wi).unit_vector())
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut rng = sampler::rng();
+
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.world_to_local(-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let coat_prob = self.coat_prob(wo);
+
+        if rng.gen::<f64>() < coat_prob {
+            // Picking the coat by its Fresnel term cancels it out of the
+            // weight of a mirror reflection
+            if distribution.is_smooth() {
+                let wi = Vec3::from(-wo.x(), -wo.y(), wo.z());
+                srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+                srec.attenuation = Color::from(1.0, 1.0, 1.0);
+                srec.specular = true;
+
+                return true;
+            }
+
+            let wm = distribution.sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+            srec.scattered = Ray::from(rec.p, frame.local_to_world(microfacet::reflect(wo, wm)));
+        } else {
+            let mut base_srec = ScatterRecord::new();
+            if !self.base.scatter(ray_in, rec, &mut base_srec) {
+                return false;
+            }
+
+            let wi = frame.world_to_local(base_srec.scattered.direction().unit_vector());
+            if wi.z() <= 0.0 {
+                return false;
+            }
+
+            if base_srec.specular {
+                srec.scattered = base_srec.scattered;
+                srec.attenuation =
+                    base_srec.attenuation * self.transmitted(wo) * self.transmitted(wi)
+                        / (1.0 - coat_prob);
+                srec.specular = true;
+
+                return true;
+            }
+
+            srec.scattered = base_srec.scattered;
+        }
+
+        // Either part could have produced the direction, so the weight uses
+        // the combined density of both
+        let pdf = self.pdf(ray_in, rec, &srec.scattered);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        srec.attenuation = self.eval(ray_in, rec, &srec.scattered) / pdf;
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new();
+        }
+
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let coat = self.coat_eval(&distribution, wo, wi);
+
+        Color::from(coat, coat, coat)
+            + self.transmitted(wo) * self.transmitted(wi) * self.base.eval(ray_in, rec, scattered)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let coat_prob = self.coat_prob(wo);
+
+        coat_prob * self.coat_pdf(&distribution, wo, wi)
+            + (1.0 - coat_prob) * self.base.pdf(ray_in, rec, scattered)
+    }
+
+    /// The coat only adds a sharp reflection on top, so the base decides how
+    /// rough the surface looks
+    fn roughness(&self) -> f64 {
+        self.base.roughness()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{HitRecord, Hittable, Interval, Ray};
+
+/// How the two sides of a CSG node are combined
+#[derive(Debug, Clone, Copy)]
+pub enum CsgOp {
+    /// Inside either side
+    Union,
+    /// Inside both sides
+    Intersection,
+    /// Inside the left side but not the right
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry: a solid made by combining two others, e.g. a
+/// sphere with a box carved out of it.
+///
+/// Both sides must be closed solids that report their intervals. Surfaces keep
+/// the material of the side they came from.
+pub struct Csg {
+    op: CsgOp,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn from(op: CsgOp, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self { op, left, right }
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        for interval in self.intervals(ray) {
+            for rec in [interval.enter, interval.exit].iter() {
+                if rec.t < t_max && rec.t > t_min {
+                    *hit_record = rec.clone();
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        combine(self.op, self.left.intervals(ray), self.right.intervals(ray))
+    }
+}
+
+/// Combine two sorted lists of intervals along the same ray.
+///
+/// We sweep through every boundary in order, keeping track of whether we're
+/// inside each side, and start or end an interval whenever that changes whether
+/// we're inside the result.
+pub fn combine(op: CsgOp, left: Vec<Interval>, right: Vec<Interval>) -> Vec<Interval> {
+    let mut boundaries: Vec<(HitRecord, bool, bool)> = vec![];
+
+    for (intervals, is_left) in [(left, true), (right, false)] {
+        for interval in intervals {
+            boundaries.push((interval.enter, is_left, true));
+            boundaries.push((interval.exit, is_left, false));
+        }
+    }
+
+    boundaries.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let mut in_left = false;
+    let mut in_right = false;
+    let mut enter: Option<HitRecord> = None;
+    let mut result = vec![];
+
+    for (mut rec, is_left, entering) in boundaries {
+        let was_inside = op.contains(in_left, in_right);
+
+        if is_left {
+            in_left = entering;
+        } else {
+            in_right = entering;
+        }
+
+        let inside = op.contains(in_left, in_right);
+
+        // The normal always faces the ray already, but the face has to be
+        // updated for boundaries that are flipped inside out, like the surface
+        // of the right side of a difference
+        if inside && !was_inside {
+            rec.front_face = true;
+            enter = Some(rec);
+        } else if !inside && was_inside {
+            rec.front_face = false;
+            result.push(Interval {
+                enter: enter.take().unwrap(),
+                exit: rec,
+            });
+        }
+    }
+
+    result
+}
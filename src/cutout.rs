@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::sampler;
+use crate::texture::Texture;
+use crate::{HitRecord, Hittable, Ray};
+
+/// How the opacity from a mask decides whether a hit counts
+#[derive(Debug, Clone, Copy)]
+pub enum AlphaTest {
+    /// Hits where the opacity is below the threshold are skipped, giving hard
+    /// edges
+    Threshold(f64),
+    /// Hits are kept with a probability of the opacity, so partly opaque
+    /// parts let some light through and edges come out smooth once averaged
+    Stochastic,
+}
+
+/// An object with holes cut in it by an opacity mask, like a leaf on a flat
+/// card or a decal. Rays carry on through the cut away parts as though
+/// nothing was there, so shadows get the holes too.
+///
+/// The mask's average over its channels is the opacity at each point.
+pub struct Cutout {
+    object: Box<dyn Hittable>,
+    mask: Arc<dyn Texture>,
+    test: AlphaTest,
+}
+
+impl Cutout {
+    pub fn from(object: Box<dyn Hittable>, mask: Arc<dyn Texture>, test: AlphaTest) -> Self {
+        Self { object, mask, test }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let c = self.mask.value(rec.u, rec.v, rec.p);
+        let alpha = (c.x() + c.y() + c.z()) / 3.0;
+
+        match self.test {
+            AlphaTest::Threshold(threshold) => alpha >= threshold,
+            AlphaTest::Stochastic => sampler::rng().gen::<f64>() < alpha,
+        }
+    }
+}
+
+impl Hittable for Cutout {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let mut t_min = t_min;
+
+        // Keep looking past each hit that lands in a hole
+        while self.object.hit(ray, t_min, t_max, hit_record) {
+            if self.is_opaque(hit_record) {
+                return true;
+            }
+            t_min = hit_record.t;
+        }
+
+        false
+    }
+}
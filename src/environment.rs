@@ -0,0 +1,216 @@
+use std::f64::consts::PI;
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::image::Image;
+use crate::sampler;
+use crate::util;
+use crate::{Color, Vec3};
+
+/// Light arriving from an environment, picked by `Environment::sample`
+pub struct EnvironmentSample {
+    /// Unit vector towards where the light comes from
+    pub direction: Vec3,
+    pub radiance: Color,
+    /// Probability density of picking the direction, with respect to solid
+    /// angle
+    pub pdf: f64,
+}
+
+/// Light from infinitely far away in every direction, seen by rays that
+/// don't hit anything
+pub trait Environment: Send + Sync {
+    /// Light arriving from the direction, which doesn't have to be normalized
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    /// Pick a direction to sample the light from, favouring the brightest
+    /// parts. Environments that are smooth enough to be found by scattered
+    /// rays alone don't need to.
+    fn sample(&self) -> Option<EnvironmentSample> {
+        None
+    }
+
+    /// The density `sample` picks the direction with
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// A blend from white at the horizon to light blue overhead
+pub struct Gradient;
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (direction.unit_vector().y() + 1.0);
+
+        (1.0 - t) * Color::from(1.0, 1.0, 1.0) + t * Color::from(0.5, 0.7, 1.0)
+    }
+}
+
+/// A high dynamic range panorama in the equirectangular (latitude-longitude)
+/// layout, wrapped around the scene.
+///
+/// Directions are sampled in proportion to how bright the map is, so small
+/// bright spots like the sun are found by direct lighting rather than the odd
+/// lucky scattered ray.
+pub struct EnvironmentMap {
+    image: Image,
+    rotation: f64,
+    scale: f64,
+    /// Running totals of how likely each row is to be picked
+    row_cdf: Vec<f64>,
+    /// Running totals of how likely each pixel in a row is to be picked,
+    /// given that row
+    pixel_cdfs: Vec<Vec<f64>>,
+    /// Sum of the weights of every pixel
+    total: f64,
+}
+
+impl EnvironmentMap {
+    /// Use an image, turned `rotation` degrees around the vertical axis and
+    /// with its values multiplied by `scale`
+    pub fn from(image: Image, rotation: f64, scale: f64) -> Self {
+        let width = image.width;
+        let height = image.height;
+
+        // Pixels near the poles cover less of the sphere, so they're weighted
+        // by the sine of their angle from the top
+        let mut pixel_cdfs = Vec::with_capacity(height);
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut total = 0.0;
+
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+
+            let mut row_total = 0.0;
+            let cdf: Vec<f64> = (0..width)
+                .map(|x| {
+                    row_total += util::luminance(image.pixel(x, y)) * sin_theta;
+                    row_total
+                })
+                .collect();
+
+            total += row_total;
+            row_cdf.push(total);
+            pixel_cdfs.push(cdf);
+        }
+
+        Self {
+            image,
+            rotation: rotation.to_radians(),
+            scale,
+            row_cdf,
+            pixel_cdfs,
+            total,
+        }
+    }
+
+    pub fn load(path: &Path, rotation: f64, scale: f64) -> io::Result<Self> {
+        Ok(Self::from(Image::load(path)?, rotation, scale))
+    }
+
+    /// Turn a direction into the map's texture coordinates, both in [0, 1)
+    /// with v = 0 at the top
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = rotate_y(direction.unit_vector(), -self.rotation);
+        let phi = d.z().atan2(d.x());
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+
+        ((phi + PI) / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = 2.0 * PI * u - PI;
+        let theta = PI * v;
+
+        rotate_y(
+            Vec3::from(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ),
+            self.rotation,
+        )
+    }
+
+    fn pixel_at(&self, u: f64, v: f64) -> (usize, usize) {
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+
+        (x, y)
+    }
+
+    /// Density of picking the pixel at (x, y), and a direction with the
+    /// given angle from the top within it
+    fn pdf_at(&self, x: usize, y: usize, theta: f64) -> f64 {
+        let sin_theta = theta.sin();
+        if self.total <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let row = &self.pixel_cdfs[y];
+        let weight = row[x] - if x > 0 { row[x - 1] } else { 0.0 };
+        let pixel_count = (self.image.width * self.image.height) as f64;
+
+        // The map covers 2π by π radians, and each bit of it covers less of
+        // the sphere by sin θ
+        (weight / self.total) * pixel_count / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(u, v);
+
+        self.scale * self.image.pixel(x, y)
+    }
+
+    fn sample(&self) -> Option<EnvironmentSample> {
+        if self.total <= 0.0 {
+            return None;
+        }
+
+        let mut rng = sampler::rng();
+
+        let y = find_interval(&self.row_cdf, rng.gen::<f64>() * self.total);
+        let row = &self.pixel_cdfs[y];
+        let x = find_interval(row, rng.gen::<f64>() * row[row.len() - 1]);
+
+        let u = (x as f64 + rng.gen::<f64>()) / self.image.width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / self.image.height as f64;
+
+        let pdf = self.pdf_at(x, y, PI * v);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(EnvironmentSample {
+            direction: self.uv_to_direction(u, v),
+            radiance: self.scale * self.image.pixel(x, y),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(u, v);
+
+        self.pdf_at(x, y, PI * v)
+    }
+}
+
+/// Index of the first running total above `value`
+fn find_interval(cdf: &[f64], value: f64) -> usize {
+    cdf.partition_point(|total| *total <= value)
+        .min(cdf.len() - 1)
+}
+
+/// Turn a vector `angle` radians around the vertical axis
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+
+    Vec3::from(cos * v.x() - sin * v.z(), v.y(), sin * v.x() + cos * v.z())
+}
@@ -0,0 +1,68 @@
+use crate::Color;
+
+/// The image being rendered, kept as running totals for each pixel until
+/// it's written out.
+///
+/// Light traced from the lights to the camera can land on any pixel, not
+/// just the one whose sample it came from, so it's kept apart as splats.
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    samples: Vec<Color>,
+    splats: Vec<Color>,
+}
+
+impl Film {
+    pub fn from(width: u32, height: u32) -> Self {
+        let count = (width * height) as usize;
+
+        Self {
+            width,
+            height,
+            samples: vec![Color::new(); count],
+            splats: vec![Color::new(); count],
+        }
+    }
+
+    /// Add a sample to the pixel `i` across and `j` up from the bottom left
+    pub fn add_sample(&mut self, i: u32, j: u32, color: Color) {
+        let index = self.index(i, j);
+        self.samples[index] += color;
+    }
+
+    /// Add light reaching the camera at (s, t) on the image, both from 0 to
+    /// 1 from the bottom left like `Camera::get_ray` takes them. `color` is
+    /// per unit of image area, and is spread over the pixel it lands on.
+    pub fn add_splat(&mut self, s: f64, t: f64, color: Color) {
+        // Pixels are laid out the same way the main loop samples them
+        let x = s * (self.width - 1) as f64;
+        let y = t * (self.height - 1) as f64;
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return;
+        }
+
+        let pixel_density = ((self.width - 1) * (self.height - 1)) as f64;
+        let index = self.index(x as u32, y as u32);
+        self.splats[index] += pixel_density * color;
+    }
+
+    /// Print the image as a plain PPM, averaging each pixel over the number
+    /// of samples taken
+    pub fn write(&self, samples_per_pixel: u32) {
+        // file header
+        println!("P3"); // specifies that colors are in ASCII
+        println!("{} {}", self.width, self.height); // columns and rows
+        println!("255"); // max color
+
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                let index = self.index(i, j);
+                (self.samples[index] + self.splats[index]).print_color(samples_per_pixel);
+            }
+        }
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        (j * self.width + i) as usize
+    }
+}
@@ -1,13 +1,40 @@
+use crate::csg::{combine, CsgOp};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::onb::Onb;
 use crate::{Color, Material, Point3, Ray, Vec3};
 
 //TODO: document all fields
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
     pub front_face: bool,
-    pub material: Material,
+    pub material: Option<Arc<dyn Material>>,
+    /// Color of the surface at the hit point for objects with per-vertex
+    /// colors, white otherwise. Diffuse materials multiply it into their albedo.
+    pub color: Color,
+    /// Texture coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
+    /// Unit vectors along the surface in the directions u and v increase,
+    /// perpendicular to the normal and each other. Together with the normal
+    /// they make the frame that normal maps are given in.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    /// Weights of the corners of the triangle that was hit, for triangle
+    /// meshes. Cleared by `set_uv`, so meshes set it after.
+    pub barycentrics: Option<Vec3>,
+    /// Index of refraction of whatever the object is sitting in, for
+    /// refracting into water rather than air. Integrators that keep track of
+    /// the objects rays are inside of set it, and it's 1 otherwise.
+    pub outside_ior: f64,
+    /// Roughness the material should be treated as having at least, so
+    /// integrators can blur sharp reflections deeper along a path. It's 0
+    /// unless they set it.
+    pub min_roughness: f64,
 }
 
 impl HitRecord {
@@ -20,22 +47,86 @@ impl HitRecord {
         }
     }
 
+    /// Set the texture coordinates, and the tangent frame from the rates the
+    /// surface moves as u and v change. Must come after the normal is set.
+    /// Surfaces without a parameterisation can pass zero vectors, which gets
+    /// them an arbitrary frame.
+    pub fn set_uv(&mut self, u: f64, v: f64, dpdu: Vec3, dpdv: Vec3) {
+        self.u = u;
+        self.v = v;
+        self.barycentrics = None;
+
+        let tangent = dpdu - dpdu.dot(self.normal) * self.normal;
+        self.tangent = if tangent.length_squared() > 1e-16 {
+            tangent.unit_vector()
+        } else {
+            Onb::from_w(self.normal).local_to_world(Vec3::from(1.0, 0.0, 0.0))
+        };
+
+        // Keep the bitangent pointing the way v increases whichever side of
+        // the surface was hit
+        self.bitangent = self.normal.cross(self.tangent);
+        if self.bitangent.dot(dpdv) < 0.0 {
+            self.bitangent = -self.bitangent;
+        }
+    }
+
     pub fn new() -> Self {
         Self {
             p: Point3::new(),
             normal: Vec3::new(),
             t: 0.0,
             front_face: false,
-            material: Material::Metal {
-                albedo: Color::new(),
-                fuzz: 0.0,
-            },
+            material: None,
+            color: Color::from(1.0, 1.0, 1.0),
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::new(),
+            bitangent: Vec3::new(),
+            barycentrics: None,
+            outside_ior: 1.0,
+            min_roughness: 0.0,
         }
     }
 }
 
+/// A stretch of a ray that lies inside a solid object, from the point where the
+/// ray enters it to the point where it leaves.
+#[derive(Clone)]
+pub struct Interval {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool;
+
+    /// Every interval along the whole ray (including behind its origin) that
+    /// lies inside the object, sorted by distance and not overlapping.
+    ///
+    /// This is what lets objects be combined with constructive solid geometry.
+    /// Objects without a well defined inside, like volumes, report none.
+    fn intervals(&self, _ray: &Ray) -> Vec<Interval> {
+        vec![]
+    }
+
+    /// Fraction of light that makes it along the ray between `t_min` and
+    /// `t_max`, as seen by shadow rays. Solid objects block it all if they're
+    /// in the way, while volumes let some through.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.hit(ray, t_min, t_max, &mut HitRecord::new()) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// How much work finding the hit takes, counted in bounding boxes and
+    /// primitives tested. Objects without an acceleration structure count
+    /// as one.
+    fn traversal_cost(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> usize {
+        1
+    }
 }
 
 pub struct HittableList {
@@ -43,10 +134,6 @@ pub struct HittableList {
 }
 
 impl HittableList {
-    pub fn clear(&mut self) {
-        self.objects.clear();
-    }
-
     pub fn push(&mut self, object: Box<dyn Hittable>) {
         self.objects.push(object);
     }
@@ -58,16 +145,7 @@ impl HittableList {
 
 impl Hittable for HittableList {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
-        let mut temp_rec: HitRecord = HitRecord {
-            p: Point3::from(0.0, 0.0, 0.0),
-            normal: Vec3::from(0.0, 0.0, 0.0),
-            t: 0.0,
-            front_face: false,
-            material: Material::Metal {
-                albedo: Color::new(),
-                fuzz: 0.0,
-            },
-        };
+        let mut temp_rec = HitRecord::new();
 
         let mut hit_anything = false;
         let mut closest = t_max;
@@ -80,24 +158,81 @@ impl Hittable for HittableList {
             }
         }
 
-        return hit_anything;
+        hit_anything
+    }
+
+    /// The union of the intervals of every object in the list
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        self.objects.iter().fold(vec![], |acc, object| {
+            combine(CsgOp::Union, acc, object.intervals(ray))
+        })
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+
+        for object in self.objects.iter() {
+            transmittance *= object.transmittance(ray, t_min, t_max);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+
+        transmittance
+    }
+
+    fn traversal_cost(&self, ray: &Ray, t_min: f64, t_max: f64) -> usize {
+        let mut rec = HitRecord::new();
+        let mut closest = t_max;
+        let mut cost = 0;
+
+        for object in self.objects.iter() {
+            cost += object.traversal_cost(ray, t_min, closest);
+            if object.hit(ray, t_min, closest, &mut rec) {
+                closest = rec.t;
+            }
+        }
+
+        cost
     }
 }
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    material: Material,
+    material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn from(center: Point3, radius: f64, material: Material) -> Self {
+    pub fn from(center: Point3, radius: f64, material: Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
             material,
         }
     }
+
+    fn record_at(&self, ray: &Ray, t: f64) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.p = ray.at(t);
+        let n = (rec.p - self.center) / self.radius;
+        rec.set_face_normal(ray, n);
+        rec.material = Some(self.material.clone());
+
+        // u goes around the sphere from -X, and v from the bottom to the top
+        let theta = (-n.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-n.z()).atan2(n.x()) + PI;
+        let dpdu = Vec3::from(n.z(), 0.0, -n.x());
+        let dpdv = Vec3::from(
+            -phi.cos() * theta.cos(),
+            theta.sin(),
+            phi.sin() * theta.cos(),
+        );
+        rec.set_uv(phi / (2.0 * PI), theta / PI, dpdu, dpdv);
+
+        rec
+    }
 }
 
 impl Hittable for Sphere {
@@ -121,23 +256,152 @@ impl Hittable for Sphere {
             let root = discriminant.sqrt();
             let temp = (-half_b - root) / a;
             if temp < t_max && temp > t_min {
-                hit_record.t = temp;
-                hit_record.p = ray.at(temp);
-                let outward_normal = (hit_record.p - self.center) / self.radius;
-                hit_record.set_face_normal(ray, outward_normal);
-                hit_record.material = self.material;
+                *hit_record = self.record_at(ray, temp);
                 return true;
             }
             let temp = (-half_b + root) / a;
             if temp < t_max && temp > t_min {
-                hit_record.t = temp;
-                hit_record.p = ray.at(temp);
-                let outward_normal = (hit_record.p - self.center) / self.radius;
-                hit_record.set_face_normal(ray, outward_normal);
-                hit_record.material = self.material;
+                *hit_record = self.record_at(ray, temp);
                 return true;
             }
         }
-        return false;
+        false
     }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let oc = ray.origin() - self.center;
+
+        let a = ray.direction().length_squared();
+        let half_b = oc.dot(ray.direction());
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+
+        if discriminant <= 0.0 {
+            return vec![];
+        }
+
+        let root = discriminant.sqrt();
+
+        vec![Interval {
+            enter: self.record_at(ray, (-half_b - root) / a),
+            exit: self.record_at(ray, (-half_b + root) / a),
+        }]
+    }
+}
+
+/// An axis aligned box between two opposite corners
+pub struct Cuboid {
+    min: Point3,
+    max: Point3,
+    material: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    pub fn from(min: Point3, max: Point3, material: Arc<dyn Material>) -> Self {
+        Cuboid { min, max, material }
+    }
+
+    /// Intersect the ray with the slabs between the faces on each axis.
+    ///
+    /// Returns the distances where the ray enters and leaves the box, along
+    /// with the outward normals of the faces it passes through there.
+    fn slabs(&self, ray: &Ray) -> Option<(f64, Vec3, f64, Vec3)> {
+        let mut t_enter = -f64::INFINITY;
+        let mut t_exit = f64::INFINITY;
+        let mut enter_normal = Vec3::new();
+        let mut exit_normal = Vec3::new();
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction()[axis];
+            let mut t0 = (self.min[axis] - ray.origin()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin()[axis]) * inv_d;
+
+            // The normal of the face the ray reaches first on this axis
+            let mut normal = -axis_vector(axis);
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+                normal = -normal;
+            }
+
+            if t0 > t_enter {
+                t_enter = t0;
+                enter_normal = normal;
+            }
+            if t1 < t_exit {
+                t_exit = t1;
+                exit_normal = -normal;
+            }
+            if t_exit <= t_enter {
+                return None;
+            }
+        }
+
+        Some((t_enter, enter_normal, t_exit, exit_normal))
+    }
+
+    fn record_at(&self, ray: &Ray, t: f64, outward_normal: Vec3) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.t = t;
+        rec.p = ray.at(t);
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+
+        // Each face is mapped flat, along the next two axes round from the
+        // one it faces
+        let axis = (0..3)
+            .max_by(|a, b| {
+                outward_normal[*a]
+                    .abs()
+                    .total_cmp(&outward_normal[*b].abs())
+            })
+            .unwrap();
+        let (axis_u, axis_v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let extent = self.max - self.min;
+        rec.set_uv(
+            (rec.p[axis_u] - self.min[axis_u]) / extent[axis_u],
+            (rec.p[axis_v] - self.min[axis_v]) / extent[axis_v],
+            axis_vector(axis_u),
+            axis_vector(axis_v),
+        );
+
+        rec
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let (t_enter, enter_normal, t_exit, exit_normal) = match self.slabs(ray) {
+            Some(slabs) => slabs,
+            None => return false,
+        };
+
+        if t_enter < t_max && t_enter > t_min {
+            *hit_record = self.record_at(ray, t_enter, enter_normal);
+            return true;
+        }
+        if t_exit < t_max && t_exit > t_min {
+            *hit_record = self.record_at(ray, t_exit, exit_normal);
+            return true;
+        }
+
+        false
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match self.slabs(ray) {
+            Some((t_enter, enter_normal, t_exit, exit_normal)) => vec![Interval {
+                enter: self.record_at(ray, t_enter, enter_normal),
+                exit: self.record_at(ray, t_exit, exit_normal),
+            }],
+            None => vec![],
+        }
+    }
+}
+
+/// Unit vector along the given axis, 0 being X
+fn axis_vector(axis: usize) -> Vec3 {
+    let mut e = [0.0; 3];
+    e[axis] = 1.0;
+
+    Vec3::from(e[0], e[1], e[2])
 }
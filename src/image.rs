@@ -0,0 +1,299 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Color;
+
+/// A grid of pixels loaded from a file, stored row by row from the top
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Image {
+    /// Load a PPM (either ASCII or binary), PFM or Radiance HDR image, picked
+    /// by the file extension. PPM values are scaled to [0, 1] but otherwise
+    /// left as they are, so they suit data like normal maps as well as colors.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("ppm") => parse_ppm(&bytes),
+            Some("pfm") => parse_pfm(&bytes),
+            Some("hdr") => parse_hdr(&bytes),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown image format: {}", path.display()),
+            )),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Read the whitespace separated fields at the start of a PPM or PFM header,
+/// skipping comments. Returns them along with where the data starts, which
+/// is after the single whitespace character ending the last field.
+fn header_fields(bytes: &[u8], count: usize) -> io::Result<(Vec<String>, usize)> {
+    let mut fields = vec![];
+    let mut pos = 0;
+
+    while fields.len() < count {
+        match bytes.get(pos) {
+            None => return Err(invalid_data("image header is cut short".to_string())),
+            Some(b'#') => {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                fields.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+            }
+        }
+    }
+
+    Ok((fields, pos + 1))
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str) -> io::Result<T> {
+    field
+        .parse()
+        .map_err(|_| invalid_data(format!("bad number in image: {}", field)))
+}
+
+/// Netpbm color images, either as ASCII (P3) or binary (P6) data
+///
+/// http://netpbm.sourceforge.net/doc/ppm.html
+fn parse_ppm(bytes: &[u8]) -> io::Result<Image> {
+    let (fields, start) = header_fields(bytes, 4)?;
+    let width: usize = parse_number(&fields[1])?;
+    let height: usize = parse_number(&fields[2])?;
+    let max_value: f64 = parse_number(&fields[3])?;
+    let count = width * height * 3;
+
+    let values: Vec<f64> = match fields[0].as_str() {
+        "P3" => String::from_utf8_lossy(bytes.get(start..).unwrap_or(&[]))
+            .split_ascii_whitespace()
+            .take(count)
+            .map(parse_number)
+            .collect::<io::Result<_>>()?,
+        "P6" if max_value < 256.0 => bytes
+            .iter()
+            .skip(start)
+            .take(count)
+            .map(|b| *b as f64)
+            .collect(),
+        "P6" => bytes
+            .get(start..)
+            .unwrap_or(&[])
+            .chunks_exact(2)
+            .take(count)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]) as f64)
+            .collect(),
+        _ => return Err(invalid_data("not a PPM image".to_string())),
+    };
+
+    if values.len() != count {
+        return Err(invalid_data("PPM image is cut short".to_string()));
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels: values
+            .chunks_exact(3)
+            .map(|c| Color::from(c[0], c[1], c[2]) / max_value)
+            .collect(),
+    })
+}
+
+/// Portable float maps, holding either color (PF) or greyscale (Pf) pixels as
+/// 32 bit floats. A negative scale means the floats are little endian, and
+/// rows are stored from the bottom up.
+///
+/// http://www.pauldebevec.com/Research/HDR/PFM/
+fn parse_pfm(bytes: &[u8]) -> io::Result<Image> {
+    let (fields, start) = header_fields(bytes, 4)?;
+    let channels = match fields[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("not a PFM image".to_string())),
+    };
+    let width: usize = parse_number(&fields[1])?;
+    let height: usize = parse_number(&fields[2])?;
+    let little_endian = parse_number::<f64>(&fields[3])? < 0.0;
+
+    let data = bytes.get(start..).unwrap_or(&[]);
+    if data.len() < width * height * channels * 4 {
+        return Err(invalid_data("PFM image is cut short".to_string()));
+    }
+
+    let read = |i: usize| {
+        let word = [
+            data[4 * i],
+            data[4 * i + 1],
+            data[4 * i + 2],
+            data[4 * i + 3],
+        ];
+        if little_endian {
+            f32::from_le_bytes(word) as f64
+        } else {
+            f32::from_be_bytes(word) as f64
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let i = (y * width + x) * channels;
+            pixels.push(if channels == 3 {
+                Color::from(read(i), read(i + 1), read(i + 2))
+            } else {
+                Color::from(read(i), read(i), read(i))
+            });
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Radiance RGBE images, where each pixel is three 8 bit mantissas sharing an
+/// exponent. Scanlines are usually run length encoded one channel at a time,
+/// but can also be stored flat.
+///
+/// https://www.graphics.cornell.edu/~bjw/rgbe.html
+fn parse_hdr(bytes: &[u8]) -> io::Result<Image> {
+    let cut_short = || invalid_data("HDR image is cut short".to_string());
+
+    // The header is lines of text up to a blank one, followed by a line
+    // giving the size
+    let mut lines = vec![];
+    let mut pos = 0;
+    loop {
+        let end = bytes[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(cut_short)?;
+        let line = String::from_utf8_lossy(&bytes[pos..pos + end]).to_string();
+        pos += end + 1;
+
+        if !lines.is_empty() && line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    if !lines[0].starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR image".to_string()));
+    }
+    if let Some(format) = lines.iter().find(|line| line.starts_with("FORMAT=")) {
+        if format != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data(format!("unsupported HDR {}", format)));
+        }
+    }
+
+    let end = bytes[pos..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(cut_short)?;
+    let size = String::from_utf8_lossy(&bytes[pos..pos + end]).to_string();
+    pos += end + 1;
+
+    let fields: Vec<&str> = size.split_ascii_whitespace().collect();
+    if fields.len() != 4 || fields[0] != "-Y" || fields[2] != "+X" {
+        return Err(invalid_data(format!(
+            "unsupported HDR orientation: {}",
+            size
+        )));
+    }
+    let height: usize = parse_number(fields[1])?;
+    let width: usize = parse_number(fields[3])?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    // Each scanline is split into red, green, blue and exponent
+    let mut channels = vec![vec![0u8; width]; 4];
+
+    for _ in 0..height {
+        let header = bytes.get(pos..pos + 4).ok_or_else(cut_short)?;
+        let encoded = (8..0x8000).contains(&width)
+            && header[0] == 2
+            && header[1] == 2
+            && ((header[2] as usize) << 8 | header[3] as usize) == width;
+
+        if encoded {
+            pos += 4;
+
+            for channel in channels.iter_mut() {
+                let mut x = 0;
+                while x < width {
+                    let count = *bytes.get(pos).ok_or_else(cut_short)? as usize;
+                    pos += 1;
+
+                    // Counts over 128 are runs of one value, the rest are
+                    // that many values in a row
+                    let (run, count) = if count > 128 {
+                        (true, count - 128)
+                    } else {
+                        (false, count)
+                    };
+                    if count == 0 || x + count > width {
+                        return Err(invalid_data("bad HDR run length".to_string()));
+                    }
+
+                    for i in 0..count {
+                        let offset = if run { 0 } else { i };
+                        channel[x + i] = *bytes.get(pos + offset).ok_or_else(cut_short)?;
+                    }
+                    pos += if run { 1 } else { count };
+                    x += count;
+                }
+            }
+        } else {
+            let flat = bytes.get(pos..pos + 4 * width).ok_or_else(cut_short)?;
+            for (x, rgbe) in flat.chunks_exact(4).enumerate() {
+                for (channel, value) in channels.iter_mut().zip(rgbe) {
+                    channel[x] = *value;
+                }
+            }
+            pos += 4 * width;
+        }
+
+        pixels.extend((0..width).map(|x| {
+            let e = channels[3][x];
+            if e == 0 {
+                Color::new()
+            } else {
+                let scale = 2f64.powi(e as i32 - 136);
+                let value = |c: usize| channels[c][x] as f64 * scale;
+                Color::from(value(0), value(1), value(2))
+            }
+        }));
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
@@ -0,0 +1,40 @@
+use crate::integrator::{spawn_ray, RayIntegrator};
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Ray, Vec3};
+
+/// Shades the first thing each ray hits by how open the space above it is:
+/// white where nothing is within `radius`, darker in creases and corners
+pub struct AmbientOcclusion {
+    radius: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn from(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl RayIntegrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut rec = HitRecord::new();
+        let white = Color::from(1.0, 1.0, 1.0);
+
+        if !scene.world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
+            return spectrum::for_ray(white, ray);
+        }
+
+        // Cosine weighted, so directions near the horizon count for less
+        let mut direction = rec.normal + Vec3::random_unit_vector();
+        if direction.length_squared() < 1e-12 {
+            direction = rec.normal;
+        }
+        let probe = spawn_ray(ray, rec.p, direction.unit_vector());
+
+        if scene.world.hit(&probe, 0.001, self.radius, &mut rec) {
+            Color::new()
+        } else {
+            spectrum::for_ray(white, ray)
+        }
+    }
+}
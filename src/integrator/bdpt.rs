@@ -0,0 +1,741 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::integrator::{
+    emit_light, environment_pdf, light_count, render_pixels, sample_environment, scattered_ray,
+    spawn_ray, Integrator,
+};
+use crate::material::{Material, ScatterRecord};
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Point3, Ray, Vec3};
+
+/// What a vertex of a path is on
+#[derive(Clone)]
+enum Kind {
+    /// The camera's lens
+    Camera,
+    /// One of the scene's lights, by index. They all sit at a single point
+    /// or shine from a single direction, so paths can never hit them.
+    Light(usize),
+    /// The environment, infinitely far away
+    Environment,
+    /// A point on an object, reached along `ray_in`
+    Surface {
+        rec: Box<HitRecord>,
+        material: Arc<dyn Material>,
+        ray_in: Ray,
+    },
+}
+
+/// A point on a path traced from the camera or from a light
+#[derive(Clone)]
+struct Vertex {
+    kind: Kind,
+    p: Point3,
+    /// Unit vector towards vertices infinitely far away, which have no
+    /// position
+    direction: Option<Vec3>,
+    /// What the path carries to the vertex, over the density of picking it
+    beta: Color,
+    /// Whether the path scattered specularly here, so it can't be joined to
+    /// another path
+    delta: bool,
+    /// Density of picking the vertex from the one before it on its path,
+    /// with respect to area, or solid angle for vertices far away
+    pdf_fwd: f64,
+    /// Density of picking the vertex from the one after it, as if the path
+    /// had been traced the other way
+    pdf_rev: f64,
+    /// Whether dispersion left only the hero wavelength following the path
+    collapsed: bool,
+}
+
+impl Vertex {
+    fn from(kind: Kind, p: Point3, beta: Color) -> Self {
+        Self {
+            kind,
+            p,
+            direction: None,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            collapsed: false,
+        }
+    }
+
+    /// A vertex infinitely far away in `direction`
+    fn distant(kind: Kind, direction: Vec3, beta: Color) -> Self {
+        Self {
+            direction: Some(direction),
+            ..Self::from(kind, Point3::new(), beta)
+        }
+    }
+
+    /// Whether another path can be joined on at the vertex
+    fn connectible(&self) -> bool {
+        match self.kind {
+            Kind::Surface { .. } => !self.delta,
+            _ => true,
+        }
+    }
+
+    /// How much light arriving in the direction is spread out over the
+    /// vertex. Only surfaces are, and only by the cosine of the angle.
+    fn cos(&self, direction: Vec3) -> f64 {
+        match &self.kind {
+            Kind::Surface { rec, material, .. } if material.on_surface() => {
+                rec.normal.dot(direction).abs()
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The BSDF at a surface for light coming in travelling along
+    /// `incoming` and leaving along `outgoing`, times the cosine of the
+    /// angle `outgoing` makes with the surface
+    fn eval(&self, incoming: Vec3, outgoing: Vec3) -> Color {
+        match &self.kind {
+            Kind::Surface {
+                rec,
+                material,
+                ray_in,
+            } => {
+                let (ray, rec, scattered) = scattering(rec, ray_in, incoming, outgoing);
+                spectrum::for_ray(material.eval(&ray, &rec, &scattered), ray_in)
+            }
+            _ => Color::new(),
+        }
+    }
+
+    /// The density of a surface scattering light coming in along `incoming`
+    /// towards `outgoing`, with respect to solid angle
+    fn scatter_pdf(&self, incoming: Vec3, outgoing: Vec3) -> f64 {
+        match &self.kind {
+            Kind::Surface {
+                rec,
+                material,
+                ray_in,
+            } => {
+                let (ray, rec, scattered) = scattering(rec, ray_in, incoming, outgoing);
+                material.pdf(&ray, &rec, &scattered)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The BSDF for light following the vertex's own path on to `next`
+    fn eval_towards(&self, next: &Vertex) -> Color {
+        match &self.kind {
+            Kind::Surface { ray_in, .. } => {
+                self.eval(ray_in.direction().unit_vector(), towards(self, next).0)
+            }
+            _ => Color::new(),
+        }
+    }
+}
+
+/// A path from the camera and one from a light, to be joined together
+struct Paths {
+    camera: Vec<Vertex>,
+    light: Vec<Vertex>,
+}
+
+/// Traces one path from the camera and one from a light for each sample,
+/// and joins every vertex of one to every vertex of the other. Each way of
+/// building a path is weighted by how likely it is to have been built that
+/// way compared to all the others, using the balance heuristic.
+///
+/// Light paths are also joined straight onto the camera. Those can land
+/// anywhere on the image, so that's how caustics seen through a diffuse
+/// surface, which paths from the camera almost never find, show up.
+///
+/// Media inside objects are left out.
+///
+/// "Robust Monte Carlo Methods for Light Transport Simulation", Veach 1997
+pub struct Bdpt {
+    max_depth: u32,
+}
+
+impl Bdpt {
+    /// Paths are cut off after `max_depth` bounces between the camera and
+    /// the light
+    pub fn from(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    fn sample(&self, ray: &Ray, scene: &Scene, camera: &Camera, film: &mut Film) -> Color {
+        let paths = Paths {
+            camera: self.camera_path(ray, scene, camera),
+            light: self.light_path(ray, scene),
+        };
+
+        // A light path is traced for every sample of every pixel, so each
+        // one only adds that fraction of what it finds to the image
+        let light_paths = (film.width * film.height) as f64;
+
+        let mut radiance = Color::new();
+        for t in 1..=paths.camera.len() {
+            for s in 0..=paths.light.len() {
+                if s + t < 2 || (s == 1 && t == 1) || (s + t - 2) as u32 > self.max_depth {
+                    continue;
+                }
+
+                let (value, position) = match self.connect(ray, scene, camera, &paths, s, t) {
+                    Some(connection) => connection,
+                    None => continue,
+                };
+
+                match position {
+                    Some((u, v)) => {
+                        let color = match ray.wavelengths() {
+                            Some(wavelengths) => spectrum::to_rgb(value, wavelengths),
+                            None => value,
+                        };
+                        film.add_splat(u, v, color / light_paths);
+                    }
+                    None => radiance += value,
+                }
+            }
+        }
+
+        radiance
+    }
+
+    fn camera_path(&self, ray: &Ray, scene: &Scene, camera: &Camera) -> Vec<Vertex> {
+        let mut path = vec![Vertex::from(
+            Kind::Camera,
+            ray.origin(),
+            Color::from(1.0, 1.0, 1.0),
+        )];
+
+        let pdf = camera.pdf_direction(ray.direction());
+        self.walk(*ray, pdf, self.max_depth + 1, true, scene, &mut path);
+
+        path
+    }
+
+    /// A path from one of the lights, or the environment, picked evenly.
+    /// `ray` gives the wavelengths to follow.
+    fn light_path(&self, ray: &Ray, scene: &Scene) -> Vec<Vertex> {
+        let emission = match emit_light(scene) {
+            Some(emission) => emission,
+            None => return vec![],
+        };
+
+        let beta = spectrum::for_ray(emission.flux, ray);
+        let direction = -emission.ray.direction().unit_vector();
+        let mut vertex = match emission.light {
+            Some(index) if scene.lights[index].is_distant() => {
+                Vertex::distant(Kind::Light(index), direction, beta)
+            }
+            Some(index) => Vertex::from(Kind::Light(index), emission.ray.origin(), beta),
+            None => Vertex::distant(Kind::Environment, direction, beta),
+        };
+        vertex.pdf_fwd = self.pdf_light_origin(&vertex, scene);
+
+        let mut path = vec![vertex];
+        let ray = spawn_ray(ray, emission.ray.origin(), emission.ray.direction());
+        self.walk(ray, 0.0, self.max_depth, false, scene, &mut path);
+
+        // Where the light first lands depends on how the light picks where
+        // its rays start, not just their direction
+        if path.len() > 1 {
+            path[1].pdf_fwd = self.pdf_light(&path[0], &path[1], scene);
+        }
+
+        path
+    }
+
+    /// Follow a ray around the scene from the last vertex of the path, which
+    /// picked it with density `pdf`, adding a vertex for each hit until
+    /// `max_vertices` have been added or the path ends. Paths from the
+    /// camera that get away end on the environment.
+    fn walk(
+        &self,
+        mut ray: Ray,
+        mut pdf: f64,
+        max_vertices: u32,
+        from_camera: bool,
+        scene: &Scene,
+        path: &mut Vec<Vertex>,
+    ) {
+        let mut beta = path[path.len() - 1].beta;
+        let mut collapsed = false;
+
+        for _ in 0..max_vertices {
+            let mut rec = HitRecord::new();
+            if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                if from_camera {
+                    let direction = ray.direction().unit_vector();
+                    let mut vertex = Vertex::distant(Kind::Environment, direction, beta);
+                    vertex.pdf_fwd = pdf;
+                    vertex.collapsed = collapsed;
+                    path.push(vertex);
+                }
+                break;
+            }
+
+            let material = rec.material.clone().expect("Hit without a material");
+            let mut srec = ScatterRecord::new();
+            let scatters = material.scatter(&ray, &rec, &mut srec);
+
+            let p = rec.p;
+            let kind = Kind::Surface {
+                rec: Box::new(rec.clone()),
+                material: material.clone(),
+                ray_in: ray,
+            };
+            let mut vertex = Vertex::from(kind, p, beta);
+            vertex.pdf_fwd = convert_density(pdf, &path[path.len() - 1], &vertex);
+            vertex.delta = scatters && srec.specular;
+            vertex.collapsed = collapsed;
+            path.push(vertex);
+
+            if !scatters {
+                break;
+            }
+
+            let (scattered, weight) = scattered_ray(&ray, &srec);
+            collapsed = collapsed || weight.y() == 0.0;
+
+            // Specular bounces can't be picked any other way, so they have
+            // no density either way
+            let pdf_rev = if srec.specular {
+                pdf = 0.0;
+                beta = beta * weight * spectrum::for_ray(srec.attenuation, &ray);
+                0.0
+            } else {
+                pdf = material.pdf(&ray, &rec, &srec.scattered);
+                if pdf <= 0.0 {
+                    break;
+                }
+
+                let f = material.eval(&ray, &rec, &srec.scattered);
+                beta = beta * weight * spectrum::for_ray(f, &ray) / pdf;
+
+                let last = &path[path.len() - 1];
+                last.scatter_pdf(
+                    -srec.scattered.direction().unit_vector(),
+                    -ray.direction().unit_vector(),
+                )
+            };
+
+            let n = path.len();
+            path[n - 2].pdf_rev = convert_density(pdf_rev, &path[n - 1], &path[n - 2]);
+
+            ray = scattered;
+        }
+    }
+
+    /// Join the first `s` vertices of the light path to the first `t` of the
+    /// camera path, returning what the whole path carries to the camera,
+    /// weighted against the other ways of building it. Paths joined straight
+    /// to the camera land somewhere else on the image, so they come with
+    /// where.
+    fn connect(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        camera: &Camera,
+        paths: &Paths,
+        s: usize,
+        t: usize,
+    ) -> Option<(Color, Option<(f64, f64)>)> {
+        let (camera_path, light_path) = (&paths.camera, &paths.light);
+        let pt = &camera_path[t - 1];
+
+        // Paths from the camera that got away can only end there
+        if t > 1 && s != 0 && pt.direction.is_some() {
+            return None;
+        }
+
+        let mut sampled = None;
+        let mut position = None;
+        let value = if s == 0 {
+            pt.beta * emitted(pt, ray, scene)
+        } else if t == 1 {
+            let qs = &light_path[s - 1];
+            if !qs.connectible() {
+                return None;
+            }
+
+            let sample = camera.sample_towards(qs.p)?;
+            let importance = sample.importance;
+            let vertex = Vertex::from(
+                Kind::Camera,
+                sample.lens,
+                Color::from(importance, importance, importance),
+            );
+            position = Some((sample.s, sample.t));
+
+            let value = qs.beta * qs.eval_towards(&vertex) * vertex.beta;
+            sampled = Some(vertex);
+            value
+        } else if s == 1 {
+            if !pt.connectible() {
+                return None;
+            }
+
+            let vertex = self.sample_light(pt, ray, scene)?;
+            let value = pt.beta * pt.eval_towards(&vertex) * vertex.beta;
+            sampled = Some(vertex);
+            value
+        } else {
+            let qs = &light_path[s - 1];
+            if !qs.connectible() || !pt.connectible() {
+                return None;
+            }
+
+            let distance_squared = towards(qs, pt).1;
+            let mut value =
+                qs.beta * qs.eval_towards(pt) * pt.eval_towards(qs) * pt.beta / distance_squared;
+
+            // Both sides having been cut down to the hero wavelength would
+            // weight it up twice
+            if qs.collapsed && pt.collapsed {
+                value /= 3.0;
+            }
+
+            value
+        };
+
+        if value == Color::new() {
+            return None;
+        }
+
+        // Check nothing's in the way of the join
+        let visibility = match s {
+            0 => 1.0,
+            1 => visibility(pt, sampled.as_ref()?, ray, scene),
+            _ if t == 1 => visibility(&light_path[s - 1], sampled.as_ref()?, ray, scene),
+            _ => visibility(&light_path[s - 1], pt, ray, scene),
+        };
+        if visibility <= 0.0 {
+            return None;
+        }
+
+        let weight = self.mis_weight(scene, camera, paths, sampled.as_ref(), s, t);
+
+        Some((visibility * weight * value, position))
+    }
+
+    /// Weight for a path made by joining `s` light vertices to `t` camera
+    /// vertices, by the balance heuristic over every way of making it. The
+    /// densities of the other ways are worked out from the ratios of the
+    /// densities of picking each vertex from either end of the path.
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        paths: &Paths,
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let (camera_path, light_path) = (&paths.camera, &paths.light);
+
+        let pt = match sampled {
+            Some(vertex) if t == 1 => vertex,
+            _ => &camera_path[t - 1],
+        };
+
+        // Glowing objects aren't among the lights that paths start from, so
+        // only paths from the camera can find them
+        if s == 0 && !matches!(pt.kind, Kind::Environment) {
+            return 1.0;
+        }
+
+        let qs = match sampled {
+            Some(vertex) if s == 1 => Some(vertex),
+            _ if s > 0 => Some(&light_path[s - 1]),
+            _ => None,
+        };
+        let pt_minus = if t > 1 {
+            Some(&camera_path[t - 2])
+        } else {
+            None
+        };
+        let qs_minus = if s > 1 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+
+        // Joining the paths changes the densities of picking the vertices at
+        // either end, and those before them, from the other end
+        let pt_rev = match qs {
+            Some(qs) => self.pdf(qs, qs_minus, pt, scene, camera),
+            None => self.pdf_light_origin(pt, scene),
+        };
+        let pt_minus_rev = pt_minus.map(|pt_minus| match qs {
+            Some(qs) => self.pdf(pt, Some(qs), pt_minus, scene, camera),
+            None => self.pdf_light(pt, pt_minus, scene),
+        });
+        let qs_rev = qs.map(|qs| self.pdf(pt, pt_minus, qs, scene, camera));
+        let qs_minus_rev = match (qs, qs_minus) {
+            (Some(qs), Some(qs_minus)) => Some(self.pdf(qs, Some(pt), qs_minus, scene, camera)),
+            _ => None,
+        };
+
+        // Deltas have no density, but cancel out in the ratios. The ends of
+        // the join aren't specular though, so a density of zero there means
+        // no other way can make the path past that point.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+
+        // Ways with fewer vertices from the camera
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            let vertex = &camera_path[i];
+            let (pdf_fwd, pdf_rev, delta) = if i == t - 1 {
+                (pt.pdf_fwd, pt_rev, false)
+            } else if i == t - 2 {
+                (vertex.pdf_fwd, pt_minus_rev.unwrap_or(0.0), vertex.delta)
+            } else {
+                (vertex.pdf_fwd, vertex.pdf_rev, vertex.delta)
+            };
+            if i + 2 >= t && pdf_rev == 0.0 {
+                break;
+            }
+
+            ratio *= remap(pdf_rev) / remap(pdf_fwd);
+            if !delta && !camera_path[i - 1].delta {
+                sum += ratio;
+            }
+        }
+
+        // Ways with fewer vertices from the light
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            let vertex = &light_path[i];
+            let (pdf_fwd, pdf_rev, delta) = if i == s - 1 {
+                (
+                    qs.map_or(0.0, |qs| qs.pdf_fwd),
+                    qs_rev.unwrap_or(0.0),
+                    false,
+                )
+            } else if i == s - 2 {
+                (vertex.pdf_fwd, qs_minus_rev.unwrap_or(0.0), vertex.delta)
+            } else {
+                (vertex.pdf_fwd, vertex.pdf_rev, vertex.delta)
+            };
+            if i + 2 >= s && pdf_rev == 0.0 {
+                break;
+            }
+
+            // The scene's lights can't be hit, so paths can't end on them
+            // any other way
+            let delta_before = if i > 0 {
+                light_path[i - 1].delta
+            } else {
+                let light = if s == 1 { qs } else { Some(vertex) };
+                matches!(light.map(|light| &light.kind), Some(Kind::Light(_)))
+            };
+
+            ratio *= remap(pdf_rev) / remap(pdf_fwd);
+            if !delta && !delta_before {
+                sum += ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+
+    /// The density of `vertex` picking `next` as the vertex after it, having
+    /// been reached from `prev`, with respect to area at `next`
+    fn pdf(
+        &self,
+        vertex: &Vertex,
+        prev: Option<&Vertex>,
+        next: &Vertex,
+        scene: &Scene,
+        camera: &Camera,
+    ) -> f64 {
+        let direction = towards(vertex, next).0;
+
+        let pdf = match vertex.kind {
+            Kind::Light(_) | Kind::Environment => return self.pdf_light(vertex, next, scene),
+            Kind::Camera => camera.pdf_direction(direction),
+            Kind::Surface { .. } => match prev {
+                Some(prev) => vertex.scatter_pdf(towards(prev, vertex).0, direction),
+                None => 0.0,
+            },
+        };
+
+        convert_density(pdf, vertex, next)
+    }
+
+    /// The density of a light sending light to `next`, with respect to area
+    /// at `next`
+    fn pdf_light(&self, light: &Vertex, next: &Vertex, scene: &Scene) -> f64 {
+        let pdf = match (&light.kind, light.direction) {
+            (Kind::Light(index), _) => {
+                scene.lights[*index].pdf_emit(next.p, scene.center, scene.radius)
+            }
+            // Only points in line with the disk light comes in from, and in
+            // front of it, are reached
+            (Kind::Environment, Some(direction)) => {
+                let offset = next.p - scene.center;
+                let along = offset.dot(direction);
+                let across = offset - along * direction;
+                if along > scene.radius || across.length_squared() > scene.radius * scene.radius {
+                    return 0.0;
+                }
+
+                1.0 / (PI * scene.radius * scene.radius)
+            }
+            _ => 0.0,
+        };
+
+        pdf * next.cos(towards(light, next).0)
+    }
+
+    /// The density of picking a light vertex to start a light path from.
+    /// Lights at a point only have one place to start, so that's just the
+    /// chance of picking the light.
+    fn pdf_light_origin(&self, light: &Vertex, scene: &Scene) -> f64 {
+        let count = light_count(scene);
+
+        match (&light.kind, light.direction) {
+            (Kind::Light(_), _) => 1.0 / count,
+            (Kind::Environment, Some(direction)) => {
+                environment_pdf(&*scene.environment, direction) / count
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Pick a light, or the environment, to join straight onto `vertex`. The
+    /// vertex on the light carries the light arriving from it.
+    fn sample_light(&self, vertex: &Vertex, ray: &Ray, scene: &Scene) -> Option<Vertex> {
+        let mut rng = sampler::rng();
+
+        let count = light_count(scene);
+        let index = ((rng.gen::<f64>() * count) as usize).min(scene.lights.len());
+
+        let mut light = if index < scene.lights.len() {
+            let sample = scene.lights[index].sample(vertex.p)?;
+            let beta = count * spectrum::for_ray(sample.irradiance, ray);
+
+            if sample.distance.is_infinite() {
+                Vertex::distant(Kind::Light(index), sample.direction, beta)
+            } else {
+                let p = vertex.p + sample.distance * sample.direction;
+                Vertex::from(Kind::Light(index), p, beta)
+            }
+        } else {
+            let (direction, radiance, pdf) = sample_environment(&*scene.environment);
+            if pdf <= 0.0 {
+                return None;
+            }
+
+            let beta = count / pdf * spectrum::for_ray(radiance, ray);
+            Vertex::distant(Kind::Environment, direction, beta)
+        };
+        light.pdf_fwd = self.pdf_light_origin(&light, scene);
+
+        Some(light)
+    }
+}
+
+impl Integrator for Bdpt {
+    fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        film: &mut Film,
+        samples_per_pixel: u32,
+        spectral: bool,
+    ) {
+        render_pixels(camera, film, samples_per_pixel, spectral, |ray, film| {
+            self.sample(ray, scene, camera, film)
+        });
+    }
+}
+
+/// Unit vector from one vertex to another, and the squared distance between
+/// them, which is taken as 1 for vertices infinitely far away
+fn towards(from: &Vertex, to: &Vertex) -> (Vec3, f64) {
+    match (from.direction, to.direction) {
+        (_, Some(direction)) => (direction, 1.0),
+        (Some(direction), None) => (-direction, 1.0),
+        (None, None) => {
+            let d = to.p - from.p;
+            let distance_squared = d.length_squared();
+            (d / distance_squared.sqrt(), distance_squared)
+        }
+    }
+}
+
+/// Turn a density of `from` picking a direction into one of picking the
+/// point `to` it leads to, with respect to area
+fn convert_density(pdf: f64, from: &Vertex, to: &Vertex) -> f64 {
+    if to.direction.is_some() {
+        return pdf;
+    }
+
+    let (direction, distance_squared) = towards(from, to);
+    pdf * to.cos(direction) / distance_squared
+}
+
+/// The hit record and rays for a material to scatter light coming in along
+/// `incoming` into `outgoing`, with the normal turned to face the light
+fn scattering(
+    rec: &HitRecord,
+    ray_in: &Ray,
+    incoming: Vec3,
+    outgoing: Vec3,
+) -> (Ray, HitRecord, Ray) {
+    let mut rec = rec.clone();
+    if rec.normal.dot(incoming) > 0.0 {
+        rec.normal = -rec.normal;
+        rec.front_face = !rec.front_face;
+    }
+
+    let ray = spawn_ray(ray_in, rec.p - incoming, incoming);
+    let scattered = spawn_ray(ray_in, rec.p, outgoing);
+
+    (ray, rec, scattered)
+}
+
+/// Light given off by the vertex at the end of a path from the camera
+fn emitted(vertex: &Vertex, ray: &Ray, scene: &Scene) -> Color {
+    match (&vertex.kind, vertex.direction) {
+        (Kind::Environment, Some(direction)) => {
+            spectrum::for_ray(scene.environment.radiance(direction), ray)
+        }
+        (
+            Kind::Surface {
+                rec,
+                material,
+                ray_in,
+            },
+            _,
+        ) => spectrum::for_ray(material.emitted(ray_in, rec), ray_in),
+        _ => Color::new(),
+    }
+}
+
+/// How much light gets through between two vertices
+fn visibility(from: &Vertex, to: &Vertex, ray: &Ray, scene: &Scene) -> f64 {
+    let (direction, distance_squared) = towards(from, to);
+    let t_max = if to.direction.is_some() {
+        f64::INFINITY
+    } else {
+        distance_squared.sqrt() - 0.001
+    };
+
+    let shadow_ray = spawn_ray(ray, from.p, direction);
+    scene.world.transmittance(&shadow_ray, 0.001, t_max)
+}
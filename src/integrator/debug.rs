@@ -0,0 +1,73 @@
+use crate::integrator::RayIntegrator;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Ray};
+
+/// Distance at which the depth view is half way to black
+const DEPTH_SCALE: f64 = 10.0;
+
+/// Cost at which the heat map turns fully red
+const MAX_COST: f64 = 256.0;
+
+/// What a debug integrator shows about the first thing each ray hits
+#[derive(Debug, Clone, Copy)]
+pub enum DebugView {
+    /// Shading normals, mapped from [-1, 1] to [0, 1]
+    Normals,
+    /// Texture coordinates in red and green, repeating every unit
+    Uvs,
+    /// Distance from the camera, from white up close fading to black
+    Depth,
+    /// Weights of a triangle's corners in red, green and blue. Everything
+    /// else is black.
+    Barycentrics,
+    /// How many bounding boxes and primitives the ray was tested against,
+    /// from blue for few, through green, to red for many
+    Heatmap,
+}
+
+/// Shows something other than light, to see what's going on in a scene
+pub struct DebugIntegrator {
+    view: DebugView,
+}
+
+impl DebugIntegrator {
+    pub fn from(view: DebugView) -> Self {
+        Self { view }
+    }
+}
+
+impl RayIntegrator for DebugIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut rec = HitRecord::new();
+        let hit = scene.world.hit(ray, 0.001, f64::INFINITY, &mut rec);
+
+        let color = match self.view {
+            DebugView::Heatmap => {
+                let cost = scene.world.traversal_cost(ray, 0.001, f64::INFINITY) as f64;
+                heat((cost.ln() / MAX_COST.ln()).clamp(0.0, 1.0))
+            }
+            _ if !hit => Color::new(),
+            DebugView::Normals => 0.5 * (rec.normal + Color::from(1.0, 1.0, 1.0)),
+            DebugView::Uvs => Color::from(rec.u - rec.u.floor(), rec.v - rec.v.floor(), 0.0),
+            DebugView::Depth => {
+                let shade = DEPTH_SCALE / (DEPTH_SCALE + rec.t * ray.direction().length());
+                Color::from(shade, shade, shade)
+            }
+            DebugView::Barycentrics => rec.barycentrics.unwrap_or_else(Color::new),
+        };
+
+        spectrum::for_ray(color, ray)
+    }
+}
+
+/// Blue at 0, green at 0.5 and red at 1
+fn heat(t: f64) -> Color {
+    if t < 0.5 {
+        let s = 2.0 * t;
+        Color::from(0.0, s, 1.0 - s)
+    } else {
+        let s = 2.0 * t - 1.0;
+        Color::from(s, 1.0 - s, 0.0)
+    }
+}
@@ -0,0 +1,183 @@
+use rand::Rng;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::integrator::{Integrator, PathTracer, RayIntegrator};
+use crate::sampler::{self, PrimarySamples};
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::util;
+use crate::Color;
+
+/// How the Markov chain explores paths
+#[derive(Debug, Clone, Copy)]
+pub struct MetropolisSettings {
+    /// Paths traced up front to work out how bright the image is overall,
+    /// and to pick one to start from
+    pub bootstrap: usize,
+    /// Standard deviation of the nudge small steps give each sample
+    pub sigma: f64,
+    /// Chance of each step being a large one, picking a whole new path
+    pub large_step: f64,
+}
+
+impl MetropolisSettings {
+    pub fn new() -> Self {
+        Self {
+            bootstrap: 100_000,
+            sigma: 0.01,
+            large_step: 0.3,
+        }
+    }
+}
+
+/// A path traced from primary samples, and where on the image it goes
+struct State {
+    s: f64,
+    t: f64,
+    color: Color,
+    luminance: f64,
+}
+
+/// Primary sample space Metropolis light transport. Every random number the
+/// path tracer uses comes from a list of primary samples, and a Markov chain
+/// wanders through them, mostly by nudging each one a little, so that once
+/// it finds a path carrying light it keeps exploring the ones around it.
+/// This finds light that's hard to reach, like through a crack or from
+/// lights behind glass, far more often than picking paths independently.
+///
+/// The chain visits paths in proportion to how bright they are, so each
+/// step counts the same and is scaled by the brightness of the whole image,
+/// which is estimated from bootstrap paths beforehand.
+///
+/// Kelemen et al., "A Simple and Robust Mutation Strategy for the
+/// Metropolis Light Transport Algorithm", 2002.
+pub struct Metropolis {
+    path: PathTracer,
+    settings: MetropolisSettings,
+}
+
+impl Metropolis {
+    pub fn from(path: PathTracer, settings: MetropolisSettings) -> Self {
+        Self { path, settings }
+    }
+
+    /// Trace a path from the samples, the first two of which pick where on
+    /// the image it goes through
+    fn trace(
+        &self,
+        samples: PrimarySamples,
+        scene: &Scene,
+        camera: &Camera,
+        film: &Film,
+        spectral: bool,
+    ) -> (State, PrimarySamples) {
+        sampler::replay(samples, || {
+            let mut rng = sampler::rng();
+
+            // The last column and row of pixels reach just past 1, the same
+            // as the main loop samples them
+            let s = rng.gen::<f64>() * film.width as f64 / (film.width - 1) as f64;
+            let t = rng.gen::<f64>() * film.height as f64 / (film.height - 1) as f64;
+
+            let ray = camera.get_ray(s, t);
+            let color = if spectral {
+                let wavelengths = spectrum::sample_wavelengths(rng.gen::<f64>());
+                let values = self
+                    .path
+                    .radiance(&ray.with_wavelengths(wavelengths), scene);
+                spectrum::to_rgb(values, wavelengths)
+            } else {
+                self.path.radiance(&ray, scene)
+            };
+
+            State {
+                s,
+                t,
+                color,
+                luminance: util::luminance(color),
+            }
+        })
+    }
+}
+
+impl Integrator for Metropolis {
+    fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        film: &mut Film,
+        samples_per_pixel: u32,
+        spectral: bool,
+    ) {
+        // Choices the chain makes for itself, which mustn't come out of the
+        // primary samples
+        let mut rng = rand::thread_rng();
+        let settings = self.settings;
+
+        eprintln!("Bootstrapping");
+        let weights: Vec<f64> = (0..settings.bootstrap)
+            .map(|seed| {
+                let samples = PrimarySamples::from(seed as u64, settings.sigma);
+                let (state, _) = self.trace(samples, scene, camera, film, spectral);
+                state.luminance.max(0.0)
+            })
+            .collect();
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return;
+        }
+        let brightness = total / settings.bootstrap as f64;
+
+        // Start from a bootstrap path picked in proportion to its brightness,
+        // as if the chain had already been running for a while
+        let mut target = rng.gen::<f64>() * total;
+        let seed = weights
+            .iter()
+            .position(|weight| {
+                target -= weight;
+                target < 0.0
+            })
+            .unwrap_or(weights.len() - 1);
+
+        let samples = PrimarySamples::from(seed as u64, settings.sigma);
+        let (mut current, mut samples) = self.trace(samples, scene, camera, film, spectral);
+        samples.accept();
+
+        let pixels = (film.width * film.height) as u64;
+        let scale = brightness / ((film.width - 1) * (film.height - 1)) as f64;
+
+        for step in 0..samples_per_pixel as u64 * pixels {
+            if step % pixels == 0 {
+                eprintln!("Passes left: {}", samples_per_pixel as u64 - step / pixels);
+            }
+
+            samples.start(rng.gen::<f64>() < settings.large_step);
+            let (proposed, returned) = self.trace(samples, scene, camera, film, spectral);
+            samples = returned;
+
+            let accept = if current.luminance > 0.0 {
+                (proposed.luminance / current.luminance).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+
+            // Both paths count, weighted by the chance of moving to the
+            // proposed one, which wastes less of the work put into it
+            if proposed.luminance > 0.0 {
+                let weight = accept * scale / proposed.luminance;
+                film.add_splat(proposed.s, proposed.t, weight * proposed.color);
+            }
+            if current.luminance > 0.0 {
+                let weight = (1.0 - accept) * scale / current.luminance;
+                film.add_splat(current.s, current.t, weight * current.color);
+            }
+
+            if rng.gen::<f64>() < accept {
+                current = proposed;
+                samples.accept();
+            }
+        }
+    }
+}
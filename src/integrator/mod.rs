@@ -0,0 +1,465 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::camera::Camera;
+use crate::environment::Environment;
+use crate::film::Film;
+use crate::material::{Material, ScatterRecord};
+use crate::medium::MediumStack;
+use crate::onb::Onb;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Point3, Ray, Vec3};
+
+mod ao;
+mod bdpt;
+mod debug;
+mod mlt;
+mod path;
+mod photon;
+mod whitted;
+
+pub use ao::AmbientOcclusion;
+pub use bdpt::Bdpt;
+pub use debug::{DebugIntegrator, DebugView};
+pub use mlt::{Metropolis, MetropolisSettings};
+pub use path::{DepthLimits, PathTracer};
+pub use photon::{PhotonMapper, PhotonSettings};
+pub use whitted::Whitted;
+
+/// A way of working out the light reaching the camera
+pub trait Integrator {
+    /// Render the scene as the camera sees it onto the film, taking
+    /// `samples_per_pixel` samples of each pixel. Spectral renders follow a
+    /// few wavelengths with each sample and turn them back into RGB.
+    fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        film: &mut Film,
+        samples_per_pixel: u32,
+        spectral: bool,
+    );
+}
+
+/// An integrator that works out the light along each camera ray on its own
+pub trait RayIntegrator {
+    /// Light arriving back along the ray. Spectral rays get a value for each
+    /// of their wavelengths rather than an RGB color.
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Color;
+}
+
+impl<T: RayIntegrator> Integrator for T {
+    fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        film: &mut Film,
+        samples_per_pixel: u32,
+        spectral: bool,
+    ) {
+        render_pixels(camera, film, samples_per_pixel, spectral, |ray, _film| {
+            self.radiance(ray, scene)
+        });
+    }
+}
+
+/// Take samples of every pixel a row at a time, adding up the light
+/// `sample` finds along camera rays through them. It gets the film too, for
+/// anything it finds landing on other pixels.
+pub fn render_pixels<F>(
+    camera: &Camera,
+    film: &mut Film,
+    samples_per_pixel: u32,
+    spectral: bool,
+    mut sample: F,
+) where
+    F: FnMut(&Ray, &mut Film) -> Color,
+{
+    let mut rng = sampler::rng();
+
+    for j in (0..film.height).rev() {
+        eprintln!("Scan lines left: {}", j);
+        for i in 0..film.width {
+            // Sampling loop. We take a bunch of sample with slight shifts in
+            // location (within 1.0 units from the current position), add them
+            // together, and then take an average. This creates a smoother look
+            // on edges
+            for _s in 0..samples_per_pixel {
+                let u = (i as f64 + rng.gen::<f64>()) / (film.width - 1) as f64;
+                let v = (j as f64 + rng.gen::<f64>()) / (film.height - 1) as f64;
+
+                let ray = camera.get_ray(u, v);
+
+                let color = if spectral {
+                    let wavelengths = spectrum::sample_wavelengths(rng.gen::<f64>());
+                    let values = sample(&ray.with_wavelengths(wavelengths), film);
+                    spectrum::to_rgb(values, wavelengths)
+                } else {
+                    sample(&ray, film)
+                };
+
+                film.add_sample(i, j, color);
+            }
+        }
+    }
+}
+
+/// A new ray carrying on from `parent`, with the same wavelengths
+pub fn spawn_ray(parent: &Ray, origin: Point3, direction: Vec3) -> Ray {
+    let ray = Ray::from(origin, direction);
+
+    match parent.wavelengths() {
+        Some(wavelengths) => ray.with_wavelengths(wavelengths),
+        None => ray,
+    }
+}
+
+/// The ray a material scattered into, and what its value has to be weighted
+/// by. Spectral rays carry on with the same wavelengths, unless they were
+/// split up by dispersion. Then only the hero wavelength can follow, taking
+/// on the weight of the other two.
+pub fn scattered_ray(ray: &Ray, srec: &ScatterRecord) -> (Ray, Color) {
+    let scattered = srec.scattered;
+
+    match ray.wavelengths() {
+        Some(wavelengths) if srec.dispersed && wavelengths.x() != wavelengths.y() => (
+            scattered.with_wavelengths(Vec3::from(
+                wavelengths.x(),
+                wavelengths.x(),
+                wavelengths.x(),
+            )),
+            Color::from(3.0, 0.0, 0.0),
+        ),
+        Some(wavelengths) => (
+            scattered.with_wavelengths(wavelengths),
+            Color::from(1.0, 1.0, 1.0),
+        ),
+        None => (scattered, Color::from(1.0, 1.0, 1.0)),
+    }
+}
+
+/// Light reaching the hit point straight from each of the scene's lights and
+/// scattered back along the ray, with shadow rays checking for anything in
+/// the way. `media` are the objects the hit point is inside of.
+pub fn sample_lights(
+    ray: &Ray,
+    rec: &HitRecord,
+    material: &dyn Material,
+    scene: &Scene,
+    media: &MediumStack,
+) -> Color {
+    let mut direct = Color::new();
+
+    for light in scene.lights.iter() {
+        let sample = match light.sample(rec.p) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        let shadow_ray = spawn_ray(ray, rec.p, sample.direction);
+
+        let visibility = shadow_transmittance(scene, &shadow_ray, sample.distance - 0.001, media);
+        if visibility.length_squared() <= 0.0 {
+            continue;
+        }
+
+        direct += visibility
+            * spectrum::for_ray(
+                material.eval(ray, rec, &shadow_ray) * sample.irradiance,
+                ray,
+            );
+    }
+
+    direct
+}
+
+/// Weight for a sample from one of two ways of picking directions, given the
+/// densities of each picking it, so that the two together count it once and
+/// whichever is better at finding it counts most
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Light reaching the hit point straight from each of the scene's lights and
+/// the environment, and scattered back along the ray. Only non-specular
+/// materials can pick it up, since the chance of a specular one lining up
+/// with a light is nil.
+pub fn direct_lighting(
+    ray: &Ray,
+    rec: &HitRecord,
+    material: &dyn Material,
+    scene: &Scene,
+    media: &MediumStack,
+) -> Color {
+    let mut direct = sample_lights(ray, rec, material, scene, media);
+
+    // Scattered rays can find the environment too, so the two are weighted
+    // against each other by how likely each is to pick the direction
+    if let Some(sample) = scene.environment.sample() {
+        let shadow_ray = spawn_ray(ray, rec.p, sample.direction);
+
+        let visibility = shadow_transmittance(scene, &shadow_ray, f64::INFINITY, media);
+        if visibility.length_squared() > 0.0 {
+            let weight = power_heuristic(sample.pdf, material.pdf(ray, rec, &shadow_ray));
+
+            direct += visibility * weight / sample.pdf
+                * spectrum::for_ray(material.eval(ray, rec, &shadow_ray) * sample.radiance, ray);
+        }
+    }
+
+    direct
+}
+
+/// Fraction of light making it along a shadow ray up to `t_max`, from a
+/// point inside the objects in `media`. Solid objects block it and volumes
+/// let some through.
+///
+/// The ray goes on through the surfaces of objects it's inside of that light
+/// doesn't notice, either because they're inside something with a higher
+/// priority or because they have the same index of refraction as what's
+/// around them. Each medium dims the light over the part of the ray inside
+/// it. Any other surface would bend the light, so it blocks the ray.
+pub fn shadow_transmittance(
+    scene: &Scene,
+    shadow_ray: &Ray,
+    t_max: f64,
+    media: &MediumStack,
+) -> Color {
+    let length = shadow_ray.direction().length();
+
+    // Nothing in the way means the ray stays in the same medium throughout
+    let visibility = scene.world.transmittance(shadow_ray, 0.001, t_max);
+    if visibility > 0.0 {
+        return visibility * segment_transmittance(media, t_max * length, shadow_ray);
+    }
+
+    let mut media = media.clone();
+    let mut transmittance = Color::from(1.0, 1.0, 1.0);
+    let mut t = 0.001;
+
+    loop {
+        let rec = next_surface(scene, shadow_ray, t, t_max);
+        let end = rec.as_ref().map_or(t_max, |rec| rec.t);
+
+        let visibility = scene.world.transmittance(shadow_ray, t, end);
+        transmittance = transmittance
+            * visibility
+            * segment_transmittance(&media, (end - t) * length, shadow_ray);
+
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return transmittance,
+        };
+        if transmittance.length_squared() <= 0.0 {
+            return transmittance;
+        }
+
+        let material = rec.material.clone().expect("Hit without a material");
+        let index_matched = material
+            .ior()
+            .is_some_and(|ior| (ior - media.outside_ior(&material, rec.front_face)).abs() < 1e-9);
+        if !media.hides(&material, rec.front_face) && !index_matched {
+            return Color::new();
+        }
+
+        if rec.front_face {
+            media.enter(&material);
+        } else {
+            media.leave(&material);
+        }
+        t = rec.t + 0.001;
+    }
+}
+
+/// Fraction of light making it through `distance` of whatever medium fills
+/// the space the ray is in
+fn segment_transmittance(media: &MediumStack, distance: f64, ray: &Ray) -> Color {
+    // A ray heading off to infinity has left every object it was inside of
+    if !distance.is_finite() {
+        return Color::from(1.0, 1.0, 1.0);
+    }
+
+    spectrum::for_ray(media.transmittance(distance), ray)
+}
+
+/// The closest surface along the ray between `t_min` and `t_max`, passing
+/// over the points volumes pick for rays to scatter at
+fn next_surface(scene: &Scene, ray: &Ray, mut t_min: f64, t_max: f64) -> Option<HitRecord> {
+    loop {
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(ray, t_min, t_max, &mut rec) {
+            return None;
+        }
+
+        match &rec.material {
+            Some(material) if !material.on_surface() => t_min = rec.t + 1e-6,
+            _ => return Some(rec),
+        }
+    }
+}
+
+/// Light leaving one of the scene's lights or the environment, picked by
+/// `emit_light`
+pub struct LightEmission {
+    /// Index of the light it left, or `None` for the environment
+    pub light: Option<usize>,
+    pub ray: Ray,
+    /// The light the ray carries, over the density of picking it
+    pub flux: Color,
+}
+
+/// Pick one of the scene's lights or the environment evenly, and a ray of
+/// light leaving it. Light from the environment comes in from a disk facing
+/// the scene, just outside the sphere around it.
+pub fn emit_light(scene: &Scene) -> Option<LightEmission> {
+    let mut rng = sampler::rng();
+
+    let count = light_count(scene);
+    let index = ((rng.gen::<f64>() * count) as usize).min(scene.lights.len());
+
+    if index < scene.lights.len() {
+        let emission = scene.lights[index].emit(scene.center, scene.radius)?;
+
+        return Some(LightEmission {
+            light: Some(index),
+            ray: emission.ray,
+            flux: count * emission.flux,
+        });
+    }
+
+    let (direction, radiance, pdf) = sample_environment(&*scene.environment);
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    let area = PI * scene.radius * scene.radius;
+    let disk = scene.radius * Vec3::random_in_unit_disk();
+    let origin =
+        scene.center + scene.radius * direction + Onb::from_w(direction).local_to_world(disk);
+
+    Some(LightEmission {
+        light: None,
+        ray: Ray::from(origin, -direction),
+        flux: count * area / pdf * radiance,
+    })
+}
+
+/// The scene's lights and the environment
+pub fn light_count(scene: &Scene) -> f64 {
+    (scene.lights.len() + 1) as f64
+}
+
+/// Pick a direction towards the environment, giving the light from there and
+/// the density of picking it. Environments that can't pick directions
+/// themselves are sampled evenly over the sphere.
+pub fn sample_environment(environment: &dyn Environment) -> (Vec3, Color, f64) {
+    match environment.sample() {
+        Some(sample) => (sample.direction, sample.radiance, sample.pdf),
+        None => {
+            let direction = Vec3::random_unit_vector();
+            (direction, environment.radiance(direction), 1.0 / (4.0 * PI))
+        }
+    }
+}
+
+/// The density `sample_environment` picks the direction with
+pub fn environment_pdf(environment: &dyn Environment, direction: Vec3) -> f64 {
+    match environment.pdf(direction) {
+        pdf if pdf > 0.0 => pdf,
+        _ => 1.0 / (4.0 * PI),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::light::PointLight;
+    use crate::material::Dielectric;
+    use crate::medium::Medium;
+    use crate::{HittableList, Sphere};
+
+    fn fog() -> Medium {
+        Medium::from_albedo(Color::from(0.9, 0.9, 0.9), Color::from(2.0, 2.0, 2.0))
+    }
+
+    /// Light reaching a point inside a medium from a point light at `light`,
+    /// scattered back towards +X
+    fn lighting(world: HittableList, media: &MediumStack, p: Point3, light: Point3) -> Color {
+        let mut scene = Scene::from(world);
+        scene.push_light(Box::new(PointLight::from(
+            light,
+            Color::from(1.0, 1.0, 1.0),
+        )));
+
+        let mut rec = HitRecord::new();
+        rec.p = p;
+        let ray = Ray::from(p + Vec3::from(1.0, 0.0, 0.0), Vec3::from(-1.0, 0.0, 0.0));
+        let phase = fog().phase_function();
+
+        direct_lighting(&ray, &rec, &phase, &scene, media)
+    }
+
+    #[test]
+    fn point_light_inside_scattering_sphere() {
+        let water: Arc<dyn Material> = Arc::new(Dielectric::from(1.33).with_medium(fog()));
+        let mut world = HittableList::new();
+        world.push(Box::new(Sphere::from(Point3::new(), 1.0, water.clone())));
+
+        let mut media = MediumStack::new();
+        media.enter(&water);
+
+        let direct = lighting(
+            world,
+            &media,
+            Point3::from(0.3, 0.0, 0.0),
+            Point3::from(-0.3, 0.0, 0.0),
+        );
+        assert!(direct.x() > 0.0);
+    }
+
+    #[test]
+    fn light_through_hidden_surface() {
+        let glass: Arc<dyn Material> =
+            Arc::new(Dielectric::from(1.5).with_medium(fog()).with_priority(1));
+        let water: Arc<dyn Material> = Arc::new(Dielectric::from(1.33));
+
+        let mut world = HittableList::new();
+        world.push(Box::new(Sphere::from(Point3::new(), 2.0, glass.clone())));
+        world.push(Box::new(Sphere::from(Point3::new(), 0.5, water)));
+
+        let mut media = MediumStack::new();
+        media.enter(&glass);
+
+        let direct = lighting(world, &media, Point3::from(1.0, 0.0, 0.0), Point3::new());
+        assert!(direct.x() > 0.0);
+    }
+
+    #[test]
+    fn light_blocked_by_refracting_surface() {
+        let water: Arc<dyn Material> = Arc::new(Dielectric::from(1.33).with_medium(fog()));
+        let mut world = HittableList::new();
+        world.push(Box::new(Sphere::from(Point3::new(), 1.0, water.clone())));
+
+        let mut media = MediumStack::new();
+        media.enter(&water);
+
+        let direct = lighting(
+            world,
+            &media,
+            Point3::from(0.3, 0.0, 0.0),
+            Point3::from(5.0, 0.0, 0.0),
+        );
+        assert_eq!(direct.length_squared(), 0.0);
+    }
+}
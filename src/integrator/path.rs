@@ -0,0 +1,378 @@
+use rand::Rng;
+
+use crate::integrator::{
+    direct_lighting, power_heuristic, scattered_ray, spawn_ray, RayIntegrator,
+};
+use crate::material::{Material, ScatterRecord};
+use crate::medium::{Interaction, MediumStack};
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Ray};
+
+/// Bounces after which paths start being cut off at random
+const ROULETTE_DEPTH: u32 = 3;
+
+/// Highest chance of a path surviving Russian roulette, so even paths that
+/// lose no energy, like those bouncing around inside glass, come to an end
+const MAX_SURVIVAL: f64 = 0.95;
+
+/// How many bounces of each kind a path can take before it's cut off
+#[derive(Debug, Clone, Copy)]
+pub struct DepthLimits {
+    /// Scattering off rough and diffuse surfaces, and inside volumes
+    pub diffuse: u32,
+    /// Perfect mirror reflections
+    pub specular: u32,
+    /// Going through a surface, into or out of an object
+    pub transmission: u32,
+}
+
+impl DepthLimits {
+    /// Generous limits that leave it to Russian roulette to end most paths
+    pub fn new() -> Self {
+        Self {
+            diffuse: 16,
+            specular: 64,
+            transmission: 64,
+        }
+    }
+}
+
+/// The kinds of bounce counted against `DepthLimits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bounce {
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+impl Bounce {
+    /// The kind of bounce a ray scattered off `material` took. Only surfaces
+    /// can be gone through: volumes have no real normal to go through, so
+    /// scattering inside one counts as diffuse whichever way it goes.
+    fn of(material: &dyn Material, rec: &HitRecord, srec: &ScatterRecord) -> Self {
+        if material.on_surface() && srec.scattered.direction().dot(rec.normal) < 0.0 {
+            Bounce::Transmission
+        } else if srec.specular {
+            Bounce::Specular
+        } else {
+            Bounce::Diffuse
+        }
+    }
+}
+
+/// How many bounces of each kind a path has taken
+#[derive(Debug, Default)]
+struct Depth {
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+}
+
+impl Depth {
+    /// Count a bounce, giving whether the path is still within its limits
+    fn add(&mut self, bounce: Bounce, limits: &DepthLimits) -> bool {
+        let (count, limit) = match bounce {
+            Bounce::Diffuse => (&mut self.diffuse, limits.diffuse),
+            Bounce::Specular => (&mut self.specular, limits.specular),
+            Bounce::Transmission => (&mut self.transmission, limits.transmission),
+        };
+        *count += 1;
+        *count <= limit
+    }
+
+    fn total(&self) -> u32 {
+        self.diffuse + self.specular + self.transmission
+    }
+}
+
+/// Follows rays from the camera as they bounce around the scene, picking up
+/// light from the lights and environment at every non-specular hit along
+/// the way.
+///
+/// After a few bounces, paths are ended at random with a chance that grows
+/// as they carry less light, and the ones that carry on are weighted up to
+/// make up for it. This keeps the estimate unbiased while wasting little
+/// time on paths that can't add much.
+///
+/// Bright light found by chance through a sharp reflection deep in a path
+/// shows up as fireflies that take a long time to average out. Clamping and
+/// regularization can both be turned on to get rid of them, at the cost of
+/// some bias: the image comes out a little darker or blurrier than it should.
+pub struct PathTracer {
+    limits: DepthLimits,
+    indirect_clamp: Option<f64>,
+    regularization: Option<f64>,
+}
+
+impl PathTracer {
+    pub fn from(limits: DepthLimits) -> Self {
+        Self {
+            limits,
+            indirect_clamp: None,
+            regularization: None,
+        }
+    }
+
+    /// Scale down light that was found by bouncing off something, rather
+    /// than seen directly or sampled from the first surface the camera sees,
+    /// so that no channel of it is brighter than `max`
+    pub fn with_indirect_clamp(mut self, max: f64) -> Self {
+        self.indirect_clamp = Some(max);
+        self
+    }
+
+    /// Treat every surface after the first non-specular bounce as at least
+    /// as rough as `roughness`. Mirrors and clear glass become rough there,
+    /// so lights can be sampled from them directly, and light they'd only
+    /// find by chance is spread over more paths.
+    pub fn with_regularization(mut self, roughness: f64) -> Self {
+        self.regularization = Some(roughness);
+        self
+    }
+
+    /// Light a path picked up, clamped if it came in indirectly
+    fn clamp(&self, light: Color, indirect: bool) -> Color {
+        match self.indirect_clamp {
+            Some(max) if indirect => {
+                let brightest = light.x().max(light.y()).max(light.z());
+                if brightest > max {
+                    light * (max / brightest)
+                } else {
+                    light
+                }
+            }
+            _ => light,
+        }
+    }
+}
+
+impl RayIntegrator for PathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Color {
+        let mut radiance = Color::new();
+        let mut throughput = Color::from(1.0, 1.0, 1.0);
+        let mut ray = *ray;
+        let mut media = MediumStack::new();
+
+        // The density the last non-specular bounce picked the ray's direction
+        // with, for weighting the environment against direct lighting
+        let mut bsdf_pdf = None;
+
+        // Whether the path has scattered off anything but a perfect mirror or
+        // glass yet, after which surfaces can be regularized
+        let mut scattered_rough = false;
+
+        let mut depth = Depth::default();
+
+        loop {
+            let mut rec = HitRecord::new();
+            let hit = scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rec);
+            let distance = if hit {
+                rec.t * ray.direction().length()
+            } else {
+                f64::INFINITY
+            };
+
+            // Whatever the ray is travelling through absorbs some of the light
+            // on its way, and scattering media might send it off somewhere
+            // else before it gets there
+            match media.current() {
+                Some(medium) if medium.scatters() => match medium.sample(&ray, distance) {
+                    Interaction::Scatter { distance, weight } => {
+                        throughput = throughput * weight;
+
+                        if !depth.add(Bounce::Diffuse, &self.limits) {
+                            break;
+                        }
+
+                        // Light reaching the point inside the medium is
+                        // picked up the same way as on a surface, with the
+                        // phase function in place of the BSDF
+                        let mut rec = HitRecord::new();
+                        rec.p = ray.at(distance / ray.direction().length());
+                        let phase = medium.phase_function();
+                        let light = throughput * direct_lighting(&ray, &rec, &phase, scene, &media);
+                        radiance += self.clamp(light, depth.total() > 1);
+                        scattered_rough = true;
+
+                        let mut srec = ScatterRecord::new();
+                        phase.scatter(&ray, &rec, &mut srec);
+                        bsdf_pdf = Some(phase.pdf(&ray, &rec, &srec.scattered));
+                        ray = spawn_ray(&ray, rec.p, srec.scattered.direction());
+
+                        if !survives_roulette(&mut throughput, depth.total()) {
+                            break;
+                        }
+                        continue;
+                    }
+                    Interaction::Pass { weight } => throughput = throughput * weight,
+                },
+                _ => {
+                    throughput = throughput * spectrum::for_ray(media.transmittance(distance), &ray)
+                }
+            }
+
+            if !hit {
+                let weight = match bsdf_pdf {
+                    Some(pdf) => power_heuristic(pdf, scene.environment.pdf(ray.direction())),
+                    None => 1.0,
+                };
+
+                let light = weight
+                    * throughput
+                    * spectrum::for_ray(scene.environment.radiance(ray.direction()), &ray);
+                radiance += self.clamp(light, depth.total() > 0);
+                break;
+            }
+
+            let material = rec.material.clone().expect("Hit without a material");
+
+            // The surface of an object inside something with a higher
+            // priority isn't there as far as light is concerned, so the ray
+            // goes straight through into or out of it
+            if media.hides(&material, rec.front_face) {
+                if rec.front_face {
+                    media.enter(&material);
+                } else {
+                    media.leave(&material);
+                }
+
+                ray = spawn_ray(&ray, rec.p, ray.direction());
+                continue;
+            }
+            rec.outside_ior = media.outside_ior(&material, rec.front_face);
+            if scattered_rough {
+                rec.min_roughness = self.regularization.unwrap_or(0.0);
+            }
+
+            let light = throughput * spectrum::for_ray(material.emitted(&ray, &rec), &ray);
+            radiance += self.clamp(light, depth.total() > 0);
+
+            let mut srec = ScatterRecord::new();
+            if !material.scatter(&ray, &rec, &mut srec) {
+                break;
+            }
+
+            // Count the bounce against the limit for its kind
+            let bounce = Bounce::of(&*material, &rec, &srec);
+            if !depth.add(bounce, &self.limits) {
+                break;
+            }
+
+            // Specular materials have to give the attenuation directly, but
+            // the rest can be weighted by their BSDF over the sampling density
+            let attenuation = if srec.specular {
+                bsdf_pdf = None;
+                srec.attenuation
+            } else {
+                let pdf = material.pdf(&ray, &rec, &srec.scattered);
+                if pdf <= 0.0 {
+                    break;
+                }
+
+                let light = throughput * direct_lighting(&ray, &rec, &*material, scene, &media);
+                radiance += self.clamp(light, depth.total() > 1);
+                bsdf_pdf = Some(pdf);
+                scattered_rough = true;
+                material.eval(&ray, &rec, &srec.scattered) / pdf
+            };
+
+            let (scattered, weight) = scattered_ray(&ray, &srec);
+            throughput = throughput * weight * spectrum::for_ray(attenuation, &ray);
+
+            // Going through the surface moves the ray into or out of the
+            // object
+            if bounce == Bounce::Transmission {
+                if rec.front_face {
+                    media.enter(&material);
+                } else {
+                    media.leave(&material);
+                }
+            }
+
+            ray = scattered;
+
+            if !survives_roulette(&mut throughput, depth.total()) {
+                break;
+            }
+        }
+
+        radiance
+    }
+}
+
+/// Once a path has taken enough bounces, end it at random with a chance that
+/// grows as it carries less light, weighting it up if it carries on
+fn survives_roulette(throughput: &mut Color, bounces: u32) -> bool {
+    if bounces < ROULETTE_DEPTH {
+        return true;
+    }
+
+    let survival = throughput
+        .x()
+        .max(throughput.y())
+        .max(throughput.z())
+        .min(MAX_SURVIVAL);
+    if sampler::rng().gen::<f64>() >= survival {
+        return false;
+    }
+
+    *throughput /= survival;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::material::{Dielectric, Lambertian};
+    use crate::volume::ConstantMedium;
+    use crate::{Point3, Sphere, Vec3};
+
+    /// Hit `object` along `ray`, scatter off it and count the bounce
+    fn bounce(object: &dyn Hittable, ray: &Ray) -> (Bounce, Depth) {
+        let mut rec = HitRecord::new();
+        assert!(object.hit(ray, 0.001, f64::INFINITY, &mut rec));
+
+        let material = rec.material.clone().unwrap();
+        let mut srec = ScatterRecord::new();
+        assert!(material.scatter(ray, &rec, &mut srec));
+
+        let bounce = Bounce::of(&*material, &rec, &srec);
+        let mut depth = Depth::default();
+        assert!(depth.add(bounce, &DepthLimits::new()));
+        (bounce, depth)
+    }
+
+    #[test]
+    fn volume_scatter_is_diffuse() {
+        let boundary = Sphere::from(
+            Point3::new(),
+            1.0,
+            Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+        );
+        let volume = ConstantMedium::from(Box::new(boundary), 1e6, Color::from(0.9, 0.9, 0.9));
+        let ray = Ray::from(Point3::from(-2.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0));
+
+        // The phase function scatters every which way, half the time against
+        // the made up normal, but none of it goes through a surface
+        for _ in 0..100 {
+            let (bounce, depth) = bounce(&volume, &ray);
+            assert_eq!(bounce, Bounce::Diffuse);
+            assert_eq!(depth.diffuse, 1);
+            assert_eq!(depth.transmission, 0);
+        }
+    }
+
+    #[test]
+    fn refraction_is_transmission() {
+        let glass = Sphere::from(Point3::new(), 1.0, Arc::new(Dielectric::from(1.0)));
+        let ray = Ray::from(Point3::from(-2.0, 0.0, 0.0), Vec3::from(1.0, 0.0, 0.0));
+
+        let (bounce, depth) = bounce(&glass, &ray);
+        assert_eq!(bounce, Bounce::Transmission);
+        assert_eq!(depth.transmission, 1);
+    }
+}
@@ -0,0 +1,415 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::integrator::{
+    direct_lighting, emit_light, power_heuristic, sample_environment, sample_lights, scattered_ray,
+    spawn_ray, Integrator,
+};
+use crate::kdtree::KdTree;
+use crate::material::{Material, ScatterRecord};
+use crate::medium::MediumStack;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Point3, Ray, Vec3};
+
+/// Bounces after which photons start being cut off at random
+const ROULETTE_DEPTH: u32 = 3;
+
+/// Highest chance of a photon surviving Russian roulette
+const MAX_SURVIVAL: f64 = 0.95;
+
+/// Roughness below which camera paths carry on past a surface rather than
+/// gathering photons there, since too few land in its sharp reflections
+const GLOSSY_ROUGHNESS: f64 = 0.5;
+
+/// How photons are shot and gathered
+#[derive(Debug, Clone, Copy)]
+pub struct PhotonSettings {
+    /// Photons shot in each pass, or one for each pixel if not given
+    pub photons: Option<usize>,
+    /// How far from each point photons are gathered from to begin with
+    pub radius: f64,
+    /// Share of the photons found in a pass that are kept as the radius
+    /// shrinks, between 0 and 1. Lower values shrink it faster, which blurs
+    /// less but leaves more noise.
+    pub alpha: f64,
+    /// Most bounces camera paths and photons take
+    pub max_depth: u32,
+}
+
+impl PhotonSettings {
+    pub fn new() -> Self {
+        Self {
+            photons: None,
+            radius: 0.25,
+            alpha: 2.0 / 3.0,
+            max_depth: 16,
+        }
+    }
+}
+
+/// Light from a light that has bounced at least once and landed on a surface
+struct Photon {
+    /// The way it was travelling
+    direction: Vec3,
+    power: Color,
+    /// Whether dispersion left only the hero wavelength to carry it
+    collapsed: bool,
+}
+
+/// Where a camera path first hit something rough, for photons landing around
+/// it to be gathered
+struct VisiblePoint {
+    rec: HitRecord,
+    material: Arc<dyn Material>,
+    ray_in: Ray,
+    /// Weight of the camera path up to it
+    beta: Color,
+}
+
+/// What's been found for a pixel over the passes so far
+struct Pixel {
+    /// Light found along camera paths and from the lights directly
+    direct: Color,
+    radius: f64,
+    /// Photons counted, cut down each time the radius shrinks
+    count: f64,
+    /// Light the photons counted carry back towards the camera
+    flux: Color,
+}
+
+/// Stochastic progressive photon mapping. Each pass traces a path from the
+/// camera through every pixel until it hits something rough, then shoots
+/// photons from the lights and gathers the ones that landed near each of
+/// those points. The radius photons are gathered from shrinks from pass to
+/// pass, so the blur this leaves fades away as passes add up.
+///
+/// Unlike tracing from the camera, this finds caustics from point lights
+/// seen through glass. Only lights and the environment shoot photons, so
+/// light from glowing objects is only seen directly or in glossy
+/// reflections.
+///
+/// Hachisuka and Jensen, "Stochastic Progressive Photon Mapping", 2009.
+pub struct PhotonMapper {
+    settings: PhotonSettings,
+}
+
+impl PhotonMapper {
+    pub fn from(settings: PhotonSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Follow a camera ray through specular and glossy bounces and volumes,
+    /// giving the light found on the way and the point photons should be
+    /// gathered around
+    fn camera_path(&self, ray: &Ray, scene: &Scene) -> (Color, Option<VisiblePoint>) {
+        let mut radiance = Color::new();
+        let mut beta = Color::from(1.0, 1.0, 1.0);
+        let mut ray = *ray;
+
+        // The density the last glossy bounce picked the ray's direction with,
+        // for weighting the environment against direct lighting
+        let mut bsdf_pdf = None;
+
+        for depth in 0..self.settings.max_depth {
+            let mut rec = HitRecord::new();
+            if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                let weight = match bsdf_pdf {
+                    Some(pdf) => power_heuristic(pdf, scene.environment.pdf(ray.direction())),
+                    None => 1.0,
+                };
+
+                radiance += weight
+                    * beta
+                    * spectrum::for_ray(scene.environment.radiance(ray.direction()), &ray);
+                break;
+            }
+
+            let material = rec.material.clone().expect("Hit without a material");
+            radiance += beta * spectrum::for_ray(material.emitted(&ray, &rec), &ray);
+
+            let mut srec = ScatterRecord::new();
+            if !material.scatter(&ray, &rec, &mut srec) {
+                break;
+            }
+
+            // Photons are only kept on surfaces, so paths carry on through
+            // volumes too. Those that run out of bounces stop wherever they are.
+            let rough = material.on_surface() && material.roughness() >= GLOSSY_ROUGHNESS;
+            let last = depth + 1 == self.settings.max_depth;
+            if !srec.specular && (rough || last) {
+                radiance += beta * full_direct_lighting(&ray, &rec, &*material, scene);
+
+                let point = if material.on_surface() {
+                    Some(VisiblePoint {
+                        rec,
+                        material,
+                        ray_in: ray,
+                        beta,
+                    })
+                } else {
+                    None
+                };
+
+                return (radiance, point);
+            }
+
+            let attenuation = if srec.specular {
+                bsdf_pdf = None;
+                srec.attenuation
+            } else {
+                let pdf = material.pdf(&ray, &rec, &srec.scattered);
+                if pdf <= 0.0 {
+                    break;
+                }
+
+                radiance +=
+                    beta * direct_lighting(&ray, &rec, &*material, scene, &MediumStack::new());
+                bsdf_pdf = Some(pdf);
+                material.eval(&ray, &rec, &srec.scattered) / pdf
+            };
+
+            let (scattered, weight) = scattered_ray(&ray, &srec);
+            beta = beta * weight * spectrum::for_ray(attenuation, &ray);
+            ray = scattered;
+        }
+
+        (radiance, None)
+    }
+
+    /// Shoot `count` photons from the lights, keeping where they land after
+    /// their first bounce. Light straight from the lights is found at the
+    /// camera's end instead.
+    fn shoot_photons(
+        &self,
+        count: usize,
+        wavelengths: Option<Vec3>,
+        scene: &Scene,
+    ) -> Vec<(Point3, Photon)> {
+        let mut rng = sampler::rng();
+        let mut photons = Vec::new();
+
+        for _ in 0..count {
+            let emission = match emit_light(scene) {
+                Some(emission) => emission,
+                None => continue,
+            };
+
+            let mut ray = match wavelengths {
+                Some(wavelengths) => emission.ray.with_wavelengths(wavelengths),
+                None => emission.ray,
+            };
+            let mut beta = spectrum::for_ray(emission.flux, &ray);
+            let start = max_component(beta);
+            if start <= 0.0 {
+                continue;
+            }
+
+            for depth in 0..self.settings.max_depth {
+                let mut rec = HitRecord::new();
+                if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                    break;
+                }
+
+                let material = rec.material.clone().expect("Hit without a material");
+                let mut srec = ScatterRecord::new();
+                if !material.scatter(&ray, &rec, &mut srec) {
+                    break;
+                }
+
+                if depth > 0 && !srec.specular && material.on_surface() {
+                    photons.push((
+                        rec.p,
+                        Photon {
+                            direction: ray.direction().unit_vector(),
+                            power: beta,
+                            collapsed: collapsed(&ray),
+                        },
+                    ));
+                }
+
+                let attenuation = if srec.specular {
+                    srec.attenuation
+                } else {
+                    let pdf = material.pdf(&ray, &rec, &srec.scattered);
+                    if pdf <= 0.0 {
+                        break;
+                    }
+
+                    material.eval(&ray, &rec, &srec.scattered) / pdf
+                };
+
+                let (scattered, weight) = scattered_ray(&ray, &srec);
+                beta = beta * weight * spectrum::for_ray(attenuation, &ray);
+                ray = scattered;
+
+                if depth + 1 >= ROULETTE_DEPTH {
+                    let survival = (max_component(beta) / start).min(MAX_SURVIVAL);
+                    if rng.gen::<f64>() >= survival {
+                        break;
+                    }
+                    beta /= survival;
+                }
+            }
+        }
+
+        photons
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        film: &mut Film,
+        samples_per_pixel: u32,
+        spectral: bool,
+    ) {
+        let mut rng = sampler::rng();
+
+        let mut pixels: Vec<Pixel> = (0..film.width * film.height)
+            .map(|_| Pixel {
+                direct: Color::new(),
+                radius: self.settings.radius,
+                count: 0.0,
+                flux: Color::new(),
+            })
+            .collect();
+        let photon_count = self.settings.photons.unwrap_or(pixels.len());
+
+        for pass in 0..samples_per_pixel {
+            eprintln!("Passes left: {}", samples_per_pixel - pass);
+
+            // Every path in a pass follows the same wavelengths, so photons
+            // carry light for the wavelengths the points they land by need
+            let wavelengths = if spectral {
+                Some(spectrum::sample_wavelengths(rng.gen::<f64>()))
+            } else {
+                None
+            };
+            let to_rgb = |values: Color| match wavelengths {
+                Some(wavelengths) => spectrum::to_rgb(values, wavelengths),
+                None => values,
+            };
+
+            let mut points = Vec::with_capacity(pixels.len());
+            for j in 0..film.height {
+                for i in 0..film.width {
+                    let u = (i as f64 + rng.gen::<f64>()) / (film.width - 1) as f64;
+                    let v = (j as f64 + rng.gen::<f64>()) / (film.height - 1) as f64;
+
+                    let ray = match wavelengths {
+                        Some(wavelengths) => camera.get_ray(u, v).with_wavelengths(wavelengths),
+                        None => camera.get_ray(u, v),
+                    };
+
+                    let (direct, point) = self.camera_path(&ray, scene);
+                    pixels[(j * film.width + i) as usize].direct += to_rgb(direct);
+                    points.push(point);
+                }
+            }
+
+            let photons = KdTree::from(self.shoot_photons(photon_count, wavelengths, scene));
+
+            for (pixel, point) in pixels.iter_mut().zip(points) {
+                let point = match point {
+                    Some(point) => point,
+                    None => continue,
+                };
+
+                let mut found = 0.0;
+                let mut flux = Color::new();
+                photons.for_each_within(point.rec.p, pixel.radius, |_, photon| {
+                    found += 1.0;
+                    flux += gather(&point, photon);
+                });
+
+                if found > 0.0 {
+                    let count = pixel.count + self.settings.alpha * found;
+                    let radius = pixel.radius * (count / (pixel.count + found)).sqrt();
+                    let shrink = (radius / pixel.radius).powi(2);
+
+                    pixel.flux = shrink * (pixel.flux + to_rgb(point.beta * flux));
+                    pixel.count = count;
+                    pixel.radius = radius;
+                }
+            }
+        }
+
+        for j in 0..film.height {
+            for i in 0..film.width {
+                let pixel = &pixels[(j * film.width + i) as usize];
+                let area = PI * pixel.radius * pixel.radius;
+
+                film.add_sample(
+                    i,
+                    j,
+                    pixel.direct + pixel.flux / (photon_count as f64 * area),
+                );
+            }
+        }
+    }
+}
+
+/// Light from a photon scattered back towards the camera at the point it
+/// landed near, per unit of projected area
+fn gather(point: &VisiblePoint, photon: &Photon) -> Color {
+    let towards = Ray::from(point.rec.p, -photon.direction);
+    let cos = point.rec.normal.dot(towards.direction()).abs();
+    if cos <= 0.0 {
+        return Color::new();
+    }
+
+    let bsdf = spectrum::for_ray(
+        point.material.eval(&point.ray_in, &point.rec, &towards),
+        &point.ray_in,
+    ) / cos;
+
+    // When dispersion left both ends with only the hero wavelength, each has
+    // weighted it up by 3 to make up for the other two
+    if photon.collapsed && collapsed(&point.ray_in) {
+        bsdf * photon.power / 3.0
+    } else {
+        bsdf * photon.power
+    }
+}
+
+/// Light reaching the hit point straight from the lights and environment,
+/// and scattered back along the ray. Camera paths stop here, so nothing else
+/// can find the environment and it takes the full weight.
+fn full_direct_lighting(
+    ray: &Ray,
+    rec: &HitRecord,
+    material: &dyn Material,
+    scene: &Scene,
+) -> Color {
+    let mut direct = sample_lights(ray, rec, material, scene, &MediumStack::new());
+
+    let (direction, radiance, pdf) = sample_environment(&*scene.environment);
+    if pdf > 0.0 {
+        let shadow_ray = spawn_ray(ray, rec.p, direction);
+
+        let visibility = scene.world.transmittance(&shadow_ray, 0.001, f64::INFINITY);
+        if visibility > 0.0 {
+            direct += visibility / pdf
+                * spectrum::for_ray(material.eval(ray, rec, &shadow_ray) * radiance, ray);
+        }
+    }
+
+    direct
+}
+
+/// Whether dispersion has left only the ray's hero wavelength
+fn collapsed(ray: &Ray) -> bool {
+    matches!(ray.wavelengths(), Some(wavelengths) if wavelengths.x() == wavelengths.y())
+}
+
+fn max_component(color: Color) -> f64 {
+    color.x().max(color.y()).max(color.z())
+}
@@ -0,0 +1,65 @@
+use std::f64::consts::PI;
+
+use crate::integrator::{sample_lights, scattered_ray, spawn_ray, RayIntegrator};
+use crate::material::ScatterRecord;
+use crate::medium::MediumStack;
+use crate::scene::Scene;
+use crate::spectrum;
+use crate::{Color, HitRecord, Hittable, Ray};
+
+/// Classic recursive ray tracing. Mirrors and glass are followed exactly,
+/// while everything else is lit by the lights alone, plus the environment
+/// straight above it as a rough stand in for the rest. Media are ignored.
+///
+/// Much faster than path tracing, but without any indirect light.
+pub struct Whitted {
+    max_depth: u32,
+}
+
+impl Whitted {
+    pub fn from(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    fn trace(&self, ray: &Ray, scene: &Scene, depth: u32) -> Color {
+        if depth == 0 {
+            return Color::new();
+        }
+
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
+            return spectrum::for_ray(scene.environment.radiance(ray.direction()), ray);
+        }
+
+        let material = rec.material.clone().expect("Hit without a material");
+        let emitted = spectrum::for_ray(material.emitted(ray, &rec), ray);
+
+        let mut srec = ScatterRecord::new();
+        if !material.scatter(ray, &rec, &mut srec) {
+            return emitted;
+        }
+
+        if srec.specular {
+            let (scattered, weight) = scattered_ray(ray, &srec);
+            let attenuation = weight * spectrum::for_ray(srec.attenuation, ray);
+
+            return emitted + attenuation * self.trace(&scattered, scene, depth - 1);
+        }
+
+        // Light from a uniformly bright environment reflected by a diffuse
+        // surface comes to π times its BSDF along the normal
+        let up = spawn_ray(ray, rec.p, rec.normal);
+        let ambient = spectrum::for_ray(
+            PI * material.eval(ray, &rec, &up) * scene.environment.radiance(rec.normal),
+            ray,
+        );
+
+        emitted + sample_lights(ray, &rec, &*material, scene, &MediumStack::new()) + ambient
+    }
+}
+
+impl RayIntegrator for Whitted {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Color {
+        self.trace(ray, scene, self.max_depth)
+    }
+}
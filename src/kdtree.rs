@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+
+use crate::Point3;
+
+/// Points with something attached to each, sorted into a balanced tree so
+/// the ones near any point can be found quickly.
+///
+/// The tree is stored flat: each range of items has its median in the
+/// middle, split along the axis stored for it, with the items on either side
+/// of it forming the two halves.
+pub struct KdTree<T> {
+    items: Vec<(Point3, T)>,
+    axes: Vec<usize>,
+}
+
+impl<T> KdTree<T> {
+    pub fn from(mut items: Vec<(Point3, T)>) -> Self {
+        let mut axes = vec![0; items.len()];
+        build(&mut items, &mut axes);
+
+        Self { items, axes }
+    }
+
+    /// Call `f` with every item within `radius` of `p`
+    pub fn for_each_within<F>(&self, p: Point3, radius: f64, mut f: F)
+    where
+        F: FnMut(Point3, &T),
+    {
+        self.search(0, self.items.len(), p, radius * radius, &mut f);
+    }
+
+    fn search<F>(&self, start: usize, end: usize, p: Point3, radius_squared: f64, f: &mut F)
+    where
+        F: FnMut(Point3, &T),
+    {
+        if start >= end {
+            return;
+        }
+
+        let middle = start + (end - start) / 2;
+        let (point, item) = &self.items[middle];
+        if (*point - p).length_squared() <= radius_squared {
+            f(*point, item);
+        }
+
+        // Search the side of the split the point is on first, and only look
+        // at the other if the sphere reaches over
+        let axis = self.axes[middle];
+        let offset = p[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+
+        self.search(near.0, near.1, p, radius_squared, f);
+        if offset * offset <= radius_squared {
+            self.search(far.0, far.1, p, radius_squared, f);
+        }
+    }
+}
+
+/// Put the median along the axis the items are most spread out on in the
+/// middle, with smaller ones before it and larger after, and do the same to
+/// each half
+fn build<T>(items: &mut [(Point3, T)], axes: &mut [usize]) {
+    if items.len() <= 1 {
+        return;
+    }
+
+    let mut min = items[0].0;
+    let mut max = items[0].0;
+    for (point, _) in items.iter() {
+        min = Point3::from(
+            min.x().min(point.x()),
+            min.y().min(point.y()),
+            min.z().min(point.z()),
+        );
+        max = Point3::from(
+            max.x().max(point.x()),
+            max.y().max(point.y()),
+            max.z().max(point.z()),
+        );
+    }
+
+    let extent = max - min;
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+        0
+    } else if extent.y() > extent.z() {
+        1
+    } else {
+        2
+    };
+
+    let middle = items.len() / 2;
+    items.select_nth_unstable_by(middle, |a, b| {
+        a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal)
+    });
+    axes[middle] = axis;
+
+    let (left_items, rest) = items.split_at_mut(middle);
+    let (left_axes, rest_axes) = axes.split_at_mut(middle);
+    build(left_items, left_axes);
+    build(&mut rest[1..], &mut rest_axes[1..]);
+}
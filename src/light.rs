@@ -0,0 +1,278 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::onb::Onb;
+use crate::sampler;
+use crate::texture::Texture;
+use crate::{Color, Point3, Ray, Vec3};
+
+/// Where light arrives at a point from, and how much of it
+pub struct LightSample {
+    /// Unit vector from the point towards the light
+    pub direction: Vec3,
+    /// Distance to the light, infinite for ones that are far away
+    pub distance: f64,
+    /// Light arriving at the point through a surface facing the light, before
+    /// anything in the way is taken into account
+    pub irradiance: Color,
+}
+
+/// Light leaving a light, picked by `Light::emit`
+pub struct Emission {
+    pub ray: Ray,
+    /// The light the ray carries, over the density of picking it
+    pub flux: Color,
+}
+
+/// A light that isn't an object in the scene, and can only be reached by
+/// sampling it directly from the points being lit.
+///
+/// Lights can also send light out into the scene, for following it the
+/// other way. Lights far away do that from a disk facing the scene, just
+/// outside a sphere around everything that matters.
+pub trait Light: Send + Sync {
+    /// Pick a direction to the light from `p`, if it reaches that far
+    fn sample(&self, p: Point3) -> Option<LightSample>;
+
+    /// Pick a ray of light leaving the light, with the scene inside the
+    /// sphere at `center` with the given radius
+    fn emit(&self, center: Point3, radius: f64) -> Option<Emission>;
+
+    /// The density of `emit` sending light through `p`, with respect to area
+    /// on a surface facing the light
+    fn pdf_emit(&self, p: Point3, center: Point3, radius: f64) -> f64;
+
+    /// Whether the light is infinitely far away, so it only has a direction
+    fn is_distant(&self) -> bool {
+        false
+    }
+}
+
+/// Shines equally in every direction from a single point, falling off with
+/// the square of the distance
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn from(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Point3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity / (distance * distance),
+        })
+    }
+
+    fn emit(&self, _center: Point3, _radius: f64) -> Option<Emission> {
+        Some(Emission {
+            ray: Ray::from(self.position, Vec3::random_unit_vector()),
+            flux: 4.0 * PI * self.intensity,
+        })
+    }
+
+    fn pdf_emit(&self, p: Point3, _center: Point3, _radius: f64) -> f64 {
+        1.0 / (4.0 * PI * (p - self.position).length_squared())
+    }
+}
+
+/// A point light shining in a cone, full strength inside the inner angle and
+/// fading out smoothly to nothing at the outer one. Angles are from the axis,
+/// in degrees.
+///
+/// A cookie texture can be projected through the cone, like a gobo in front
+/// of a stage light, with the whole texture covering the outer angle.
+pub struct SpotLight {
+    position: Point3,
+    frame: Onb,
+    intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+    tan_outer: f64,
+    cookie: Option<Arc<dyn Texture>>,
+}
+
+impl SpotLight {
+    pub fn from(
+        position: Point3,
+        target: Point3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        Self {
+            position,
+            frame: Onb::from_w(target - position),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            tan_outer: outer_angle.to_radians().tan(),
+            cookie: None,
+        }
+    }
+
+    pub fn with_cookie(mut self, cookie: Arc<dyn Texture>) -> Self {
+        self.cookie = Some(cookie);
+        self
+    }
+
+    /// Light shining in a direction given in the light's frame, towards `p`
+    fn intensity(&self, local: Vec3, p: Point3) -> Color {
+        if local.z() <= self.cos_outer {
+            return Color::new();
+        }
+
+        let t = ((local.z() - self.cos_outer) / (self.cos_inner - self.cos_outer)).min(1.0);
+        let mut intensity = t * t * (3.0 - 2.0 * t) * self.intensity;
+
+        if let Some(cookie) = &self.cookie {
+            // Project onto a plane in front of the light, where the outer
+            // cone meets the edges of the texture
+            let u = 0.5 + 0.5 * local.x() / (local.z() * self.tan_outer);
+            let v = 0.5 + 0.5 * local.y() / (local.z() * self.tan_outer);
+            intensity = intensity * cookie.value(u, v, p);
+        }
+
+        intensity
+    }
+
+    /// Solid angle of the outer cone
+    fn cone_solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.cos_outer)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Point3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+
+        let local = self.frame.world_to_local(-direction);
+        if local.z() <= self.cos_outer {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity(local, p) / (distance * distance),
+        })
+    }
+
+    fn emit(&self, _center: Point3, _radius: f64) -> Option<Emission> {
+        let mut rng = sampler::rng();
+
+        // Uniformly pick a direction within the outer cone
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_outer);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let local = Vec3::from(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = self.frame.local_to_world(local);
+
+        Some(Emission {
+            ray: Ray::from(self.position, direction),
+            flux: self.cone_solid_angle() * self.intensity(local, self.position + direction),
+        })
+    }
+
+    fn pdf_emit(&self, p: Point3, _center: Point3, _radius: f64) -> f64 {
+        let to_point = p - self.position;
+        let distance_squared = to_point.length_squared();
+
+        if self.frame.world_to_local(to_point).z() <= self.cos_outer * distance_squared.sqrt() {
+            return 0.0;
+        }
+
+        1.0 / (self.cone_solid_angle() * distance_squared)
+    }
+}
+
+/// Light from very far away, like the sun, arriving from a small disk of
+/// directions `angular_radius` degrees across. A radius of zero gives
+/// perfectly sharp shadows.
+pub struct DirectionalLight {
+    frame: Onb,
+    irradiance: Color,
+    cos_max: f64,
+}
+
+impl DirectionalLight {
+    /// `direction` points towards the light
+    pub fn from(direction: Vec3, irradiance: Color, angular_radius: f64) -> Self {
+        Self {
+            frame: Onb::from_w(direction),
+            irradiance,
+            cos_max: angular_radius.to_radians().cos(),
+        }
+    }
+}
+
+impl DirectionalLight {
+    /// Uniformly pick a direction towards the light within its disk
+    fn sample_direction(&self) -> Vec3 {
+        let mut rng = sampler::rng();
+
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+
+        self.frame.local_to_world(Vec3::from(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Point3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.sample_direction(),
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+
+    fn emit(&self, center: Point3, radius: f64) -> Option<Emission> {
+        let direction = self.sample_direction();
+        let disk = radius * Vec3::random_in_unit_disk();
+        let frame = Onb::from_w(direction);
+
+        Some(Emission {
+            ray: Ray::from(
+                center + radius * direction + frame.local_to_world(disk),
+                -direction,
+            ),
+            flux: PI * radius * radius * self.irradiance,
+        })
+    }
+
+    fn pdf_emit(&self, p: Point3, center: Point3, radius: f64) -> f64 {
+        // Points off to the side of the disk, or behind it, are never reached
+        let local = self.frame.world_to_local(p - center);
+        if local.z() > radius || local.x() * local.x() + local.y() * local.y() > radius * radius {
+            return 0.0;
+        }
+
+        1.0 / (PI * radius * radius)
+    }
+
+    fn is_distant(&self) -> bool {
+        true
+    }
+}
@@ -1,36 +1,88 @@
 use std::f64;
+use std::path::Path;
+use std::sync::Arc;
 
 use rand::Rng;
 
+mod aabb;
+mod bump;
 mod camera;
+mod coated;
+mod csg;
+mod cutout;
+mod environment;
+mod film;
 mod hittable;
+mod image;
+mod integrator;
+mod kdtree;
+mod light;
 mod material;
+mod medium;
+mod mesh;
+mod microfacet;
+mod onb;
+mod perlin;
+mod ply;
+mod principled;
 mod ray;
+mod sampler;
+mod scene;
+mod sdf;
+mod sky;
+mod spectrum;
+mod stl;
+mod texture;
 mod util;
 mod vec3;
+mod volume;
 
+use bump::{Detail, Perturbed};
 use camera::*;
+use coated::Coated;
+use csg::{Csg, CsgOp};
+use cutout::{AlphaTest, Cutout};
+use environment::EnvironmentMap;
+use film::Film;
 use hittable::*;
-use material::Material;
+use integrator::{
+    AmbientOcclusion, Bdpt, DebugIntegrator, DebugView, DepthLimits, Integrator, Metropolis,
+    MetropolisSettings, PathTracer, PhotonMapper, PhotonSettings, Whitted,
+};
+use light::{DirectionalLight, PointLight, SpotLight};
+use material::{
+    Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, RoughDielectric,
+    Subsurface,
+};
+use medium::Medium;
+use mesh::{MeshData, TriangleMesh};
+use principled::{Principled, PrincipledParams};
 use ray::Ray;
+use scene::Scene;
+use sdf::{
+    BoxSdf, CapsuleSdf, Mandelbulb, Repeat, SdfObject, SmoothSubtract, SmoothUnion, SphereSdf,
+    TorusSdf,
+};
+use sky::PhysicalSky;
+use spectrum::Dispersion;
+use texture::{Checker, ImageTexture, NoiseTexture, Texture};
 use vec3::{Color, Point3, Vec3};
+use volume::{ConstantMedium, Density, HeterogeneousMedium, NoiseDensity, VoxelGrid};
 
 //TODO: once finished, randomly generate sphere to place around
 
 //TODO: make these adjustable - cli args
-const SAMPLES_PER_PIXEL: usize = 100;
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMAGE_WIDTH: usize = 384;
-const IMAGE_HEIGHT: usize = ((IMAGE_WIDTH as f64) / ASPECT_RATIO) as usize;
 const MAX_DEPTH: u32 = 50;
+/// Bidirectional paths join every vertex from the camera to every one from
+/// the light, so they're kept much shorter
+const BDPT_MAX_DEPTH: u32 = 10;
 
 fn random_scene() -> HittableList {
     let mut rng = rand::thread_rng();
     let mut world = HittableList::new();
 
-    let ground_material = Material::Lambertian {
-        albedo: Color::from(0.5, 0.5, 0.5),
-    };
+    let ground_material = Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5)));
 
     world.push(Box::new(Sphere::from(
         Point3::from(0.0, -1000.0, 0.0),
@@ -51,23 +103,18 @@ fn random_scene() -> HittableList {
                 continue;
             }
 
-            let mat: Material;
+            let mat: Arc<dyn Material>;
 
             if mat_prob < 0.8 {
                 // diffuse
-                mat = Material::Lambertian {
-                    albedo: Color::random() * Color::random(),
-                };
-                world.push(Box::new(Sphere::from(center, 0.2, mat)));
+                mat = Arc::new(Lambertian::from(Color::random() * Color::random()));
             } else if mat_prob < 0.95 {
                 // metal
                 let albedo = Color::random_range(0.0, 0.5);
                 let fuzz = rng.gen_range(0.0, 0.5);
-                mat = Material::Metal { albedo, fuzz };
-
-                world.push(Box::new(Sphere::from(center, 0.2, mat)));
+                mat = Arc::new(Metal::from(albedo, fuzz));
             } else {
-                mat = Material::Dielectric { ref_idx: 1.5 };
+                mat = Arc::new(Dielectric::from(1.5));
             }
 
             world.push(Box::new(Sphere::from(center, 0.2, mat)));
@@ -77,86 +124,1152 @@ fn random_scene() -> HittableList {
     world.push(Box::new(Sphere::from(
         Point3::from(0.0, 1.0, 0.0),
         1.0,
-        Material::Dielectric { ref_idx: 1.5 },
+        Arc::new(Dielectric::from(1.5)),
     )));
 
     world.push(Box::new(Sphere::from(
         Point3::from(-4.0, 1.0, 0.0),
         1.0,
-        Material::Lambertian {
-            albedo: Color::random(),
-        },
+        Arc::new(Lambertian::from(Color::random())),
     )));
 
     world.push(Box::new(Sphere::from(
         Point3::from(4.0, 1.0, 0.0),
         1.0,
-        Material::Metal {
-            albedo: Color::from(0.7, 0.6, 0.5),
-            fuzz: 0.0,
-        },
+        Arc::new(Metal::from(Color::from(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    world
+}
+
+/// A few spheres sitting in a bank of fog, with a ball of dark smoke and a
+/// glass sphere filled with white smoke
+fn smoke_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Metal::from(Color::from(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    world.push(Box::new(ConstantMedium::from(
+        Box::new(Sphere::from(
+            Point3::from(-4.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Isotropic::from(Color::new())),
+        )),
+        2.0,
+        Color::from(0.1, 0.1, 0.1),
+    )));
+
+    // The glass shell is slightly larger than the smoke it holds, so the smoke
+    // boundary never coincides with the glass
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::from(1.5)),
+    )));
+
+    world.push(Box::new(ConstantMedium::from(
+        Box::new(Sphere::from(
+            Point3::from(0.0, 1.0, 0.0),
+            0.95,
+            Arc::new(Isotropic::from(Color::new())),
+        )),
+        4.0,
+        Color::from(0.9, 0.9, 0.9),
+    )));
+
+    // Thin fog surrounding the whole scene
+    world.push(Box::new(ConstantMedium::from(
+        Box::new(Sphere::from(
+            Point3::new(),
+            50.0,
+            Arc::new(Isotropic::from(Color::new())),
+        )),
+        0.01,
+        Color::from(1.0, 1.0, 1.0),
+    )));
+
+    world
+}
+
+/// A cloud of turbulent noise next to a glowing ember, or a voxel grid loaded
+/// from `grid_path` in place of the cloud
+fn volume_scene(grid_path: Option<&str>) -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let cloud: Box<dyn Density> = match grid_path {
+        Some(path) => Box::new(
+            VoxelGrid::load(
+                Path::new(path),
+                Point3::from(-1.5, 0.0, -1.5),
+                Point3::from(1.5, 3.0, 1.5),
+            )
+            .expect("Failed to load voxel grid"),
+        ),
+        None => Box::new(NoiseDensity::from(20.0, 1.5)),
+    };
+
+    // Encloses the whole voxel grid box
+    world.push(Box::new(HeterogeneousMedium::from(
+        Box::new(Sphere::from(
+            Point3::from(0.0, 1.5, 0.0),
+            2.6,
+            Arc::new(Isotropic::from(Color::new())),
+        )),
+        cloud,
+        0.1,
+        1.0,
+        Color::new(),
+    )));
+
+    world.push(Box::new(HeterogeneousMedium::from(
+        Box::new(Sphere::from(
+            Point3::from(4.0, 1.0, 0.0),
+            1.0,
+            Arc::new(Isotropic::from(Color::new())),
+        )),
+        Box::new(NoiseDensity::from(10.0, 3.0)),
+        1.0,
+        0.2,
+        Color::from(2.0, 0.8, 0.2),
+    )));
+
+    world
+}
+
+/// Solids built with constructive solid geometry: a sphere with a box carved
+/// out of it, a glass lens made from two overlapping spheres, and a metal ball
+/// stuck on a cube
+fn csg_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let red = Arc::new(Lambertian::from(Color::from(0.7, 0.1, 0.1)));
+    world.push(Box::new(Csg::from(
+        CsgOp::Difference,
+        Box::new(Sphere::from(Point3::from(0.0, 1.0, -2.5), 1.0, red.clone())),
+        Box::new(Cuboid::from(
+            Point3::from(0.0, 1.0, -2.5),
+            Point3::from(2.0, 3.0, -0.5),
+            red,
+        )),
+    )));
+
+    let glass = Arc::new(Dielectric::from(1.5));
+    world.push(Box::new(Csg::from(
+        CsgOp::Intersection,
+        Box::new(Sphere::from(
+            Point3::from(-1.5, 1.4, 0.0),
+            2.0,
+            glass.clone(),
+        )),
+        Box::new(Sphere::from(Point3::from(1.5, 1.4, 0.0), 2.0, glass)),
+    )));
+
+    world.push(Box::new(Csg::from(
+        CsgOp::Union,
+        Box::new(Cuboid::from(
+            Point3::from(-0.7, 0.0, 1.8),
+            Point3::from(0.7, 1.4, 3.2),
+            Arc::new(Lambertian::from(Color::from(0.2, 0.3, 0.6))),
+        )),
+        Box::new(Sphere::from(
+            Point3::from(0.0, 1.4, 2.5),
+            0.6,
+            Arc::new(Metal::from(Color::from(0.7, 0.6, 0.5), 0.0)),
+        )),
+    )));
+
+    world
+}
+
+/// Shapes rendered by sphere tracing distance fields: a Mandelbulb in the
+/// middle, surrounded by a few built in shapes and a rippled sphere defined by a
+/// closure, on top of an endless grid of tiny spheres
+fn sdf_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(Repeat::from(
+            Box::new(SphereSdf::from(Point3::new(), 0.1)),
+            Vec3::from(0.5, 0.0, 0.5),
+        )),
+        Arc::new(Metal::from(Color::from(0.8, 0.8, 0.8), 0.1)),
+        1.0,
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(Mandelbulb::from(Point3::from(0.0, 1.2, 0.0), 1.0, 8.0, 12)),
+        Arc::new(Lambertian::from(Color::from(0.8, 0.5, 0.2))),
+        0.8,
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(TorusSdf::from(Point3::from(1.0, 0.25, -3.0), 0.6, 0.25)),
+        Arc::new(Metal::from(Color::from(0.8, 0.7, 0.3), 0.05)),
+        1.0,
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(CapsuleSdf::from(
+            Point3::from(-1.5, 0.3, -2.0),
+            Point3::from(-0.5, 1.5, -2.5),
+            0.3,
+        )),
+        Arc::new(Lambertian::from(Color::from(0.1, 0.5, 0.2))),
+        1.0,
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(SmoothUnion::from(
+            Box::new(SphereSdf::from(Point3::from(1.0, 0.9, 2.5), 0.5)),
+            Box::new(BoxSdf::from(
+                Point3::from(1.0, 0.4, 2.5),
+                Vec3::from(0.6, 0.4, 0.6),
+            )),
+            0.3,
+        )),
+        Arc::new(Dielectric::from(1.5)),
+        1.0,
+    )));
+
+    world.push(Box::new(SdfObject::from(
+        Box::new(SmoothSubtract::from(
+            Box::new(BoxSdf::from(
+                Point3::from(-1.0, 0.5, 3.5),
+                Vec3::from(0.5, 0.5, 0.5),
+            )),
+            Box::new(SphereSdf::from(Point3::from(-1.0, 1.0, 3.5), 0.6)),
+            0.1,
+        )),
+        Arc::new(Lambertian::from(Color::from(0.2, 0.3, 0.7))),
+        1.0,
     )));
 
+    // The ripples make the field overestimate distances a little, so step
+    // more carefully
+    let rippled_center = Point3::from(2.5, 0.6, 0.0);
+    world.push(Box::new(SdfObject::from(
+        Box::new(move |p: Point3| {
+            let q = 12.0 * (p - rippled_center);
+            (p - rippled_center).length() - 0.5 + 0.03 * q.x().sin() * q.y().sin() * q.z().sin()
+        }),
+        Arc::new(Lambertian::from(Color::from(0.7, 0.2, 0.5))),
+        0.5,
+    )));
+
+    world
+}
+
+/// A mesh loaded from a PLY or STL file, scaled to fit next to a couple of
+/// spheres. Any vertex colors show through its white Lambertian material.
+fn mesh_scene(mesh_path: Option<&str>) -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let path = mesh_path.expect("The mesh scene needs the path to a PLY or STL file");
+    let mut data = MeshData::load(Path::new(path)).expect("Failed to load mesh");
+    data.fit(Point3::from(0.0, 1.0, 0.0), 2.0);
+
+    world.push(Box::new(TriangleMesh::from(
+        data,
+        Arc::new(Lambertian::from(Color::from(0.9, 0.9, 0.9))),
+    )));
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, 0.5, -2.5),
+        0.5,
+        Arc::new(Dielectric::from(1.5)),
+    )));
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, 0.5, 2.5),
+        0.5,
+        Arc::new(Metal::from(Color::from(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    world
+}
+
+/// Spheres of each metal in the built in table, in front of a row of gold
+/// spheres going from perfectly smooth to very rough
+fn metals_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    for (i, name) in ["gold", "copper", "aluminium", "silver"].iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(-1.0, 0.7, -3.3 + 2.2 * i as f64),
+            0.7,
+            Arc::new(Conductor::named(name, 0.2).unwrap()),
+        )));
+    }
+
+    for i in 0..5 {
+        world.push(Box::new(Sphere::from(
+            Point3::from(2.0, 0.3, -2.0 + i as f64),
+            0.3,
+            Arc::new(Conductor::named("gold", 0.15 * i as f64).unwrap()),
+        )));
+    }
+
+    world
+}
+
+/// Glass spheres going from perfectly smooth to heavily frosted, in front of
+/// a row of colored spheres that they blur more and more
+fn frosted_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    for i in 0..4 {
+        let z = -3.3 + 2.2 * i as f64;
+
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.8, z),
+            0.8,
+            Arc::new(RoughDielectric::from(1.5, [0.0, 0.1, 0.25, 0.5][i])),
+        )));
+
+        for (j, color) in [
+            Color::from(0.8, 0.1, 0.1),
+            Color::from(0.1, 0.8, 0.1),
+            Color::from(0.1, 0.1, 0.8),
+        ]
+        .iter()
+        .enumerate()
+        {
+            world.push(Box::new(Sphere::from(
+                Point3::from(-2.5, 0.3, z - 0.6 + 0.6 * j as f64),
+                0.25,
+                Arc::new(Lambertian::from(*color)),
+            )));
+        }
+    }
+
     world
 }
 
-/// Get the color of the ray so that we can get a blue to white gradient
-fn ray_color<T: Hittable>(ray: &Ray, world: &T, depth: u32) -> Color {
-    let mut rec = HitRecord::new();
+/// Spheres of the same tinted glass in increasing sizes, which get darker the
+/// thicker they are, next to a frosted one and a clear ball holding a tinted
+/// core inside it
+fn tinted_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let green = Medium::tinted(Color::from(0.3, 0.8, 0.4), 1.0);
 
-    if depth <= 0 {
-        return Color::new();
+    for (i, radius) in [0.3, 0.6, 0.9].iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, *radius, -3.0 + 1.8 * i as f64),
+            *radius,
+            Arc::new(Dielectric::from(1.5).with_medium(green)),
+        )));
     }
 
-    // Check if the given object is going to be hit by the given ray
-    if world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
-        let mut scattered = Ray::new();
-        let mut attenuation = Color::new();
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, 0.7, 2.0),
+        0.7,
+        Arc::new(
+            RoughDielectric::from(1.5, 0.2)
+                .with_medium(Medium::tinted(Color::from(0.9, 0.5, 0.2), 1.0)),
+        ),
+    )));
+
+    // The amber core sits inside a clear ball, so rays have to enter and leave
+    // both in turn
+    world.push(Box::new(Sphere::from(
+        Point3::from(2.5, 0.6, 0.0),
+        0.6,
+        Arc::new(Dielectric::from(1.5).with_medium(Medium::from(Color::new()))),
+    )));
+    world.push(Box::new(Sphere::from(
+        Point3::from(2.5, 0.6, 0.0),
+        0.3,
+        Arc::new(
+            Dielectric::from(1.2).with_medium(Medium::tinted(Color::from(0.9, 0.6, 0.1), 0.2)),
+        ),
+    )));
+
+    world
+}
+
+/// A glass of slightly murky water with ice floating in it, next to a ball of
+/// haze, lit from behind by the sun. The water overlaps the glass a little
+/// and the ice sits in the water, so priorities decide which of them light
+/// goes by where they meet.
+fn water_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    // The inside and outside of the glass share a material, so light coming
+    // out through either is known to have left the same object
+    let glass: Arc<dyn Material> = Arc::new(Dielectric::from(1.5).with_priority(2));
+    world.push(Box::new(Csg::from(
+        CsgOp::Difference,
+        Box::new(Cuboid::from(
+            Point3::from(-0.8, 0.0, -0.8),
+            Point3::from(0.8, 2.2, 0.8),
+            glass.clone(),
+        )),
+        Box::new(Cuboid::from(
+            Point3::from(-0.7, 0.1, -0.7),
+            Point3::from(0.7, 2.5, 0.7),
+            glass,
+        )),
+    )));
+
+    // Light scattering in the water mostly carries on the way it was going
+    let water = Medium::from_albedo(Color::from(0.9, 0.95, 0.99), Color::from(6.0, 8.0, 10.0))
+        .with_anisotropy(0.8);
+    world.push(Box::new(Cuboid::from(
+        Point3::from(-0.72, 0.08, -0.72),
+        Point3::from(0.72, 1.6, 0.72),
+        Arc::new(Dielectric::from(1.33).with_medium(water)),
+    )));
+
+    let ice: Arc<dyn Material> = Arc::new(
+        RoughDielectric::from(1.31, 0.1)
+            .with_medium(Medium::from_albedo(
+                Color::from(0.99, 0.99, 0.99),
+                Color::from(2.0, 2.0, 2.0),
+            ))
+            .with_priority(1),
+    );
+    world.push(Box::new(Cuboid::from(
+        Point3::from(-0.55, 1.3, -0.5),
+        Point3::from(-0.05, 1.8, 0.0),
+        ice.clone(),
+    )));
+    world.push(Box::new(Cuboid::from(
+        Point3::from(0.05, 1.35, 0.05),
+        Point3::from(0.5, 1.8, 0.5),
+        ice,
+    )));
+
+    world.push(Box::new(
+        ConstantMedium::from(
+            Box::new(Sphere::from(
+                Point3::from(0.0, 0.8, 2.2),
+                0.8,
+                Arc::new(Isotropic::from(Color::new())),
+            )),
+            1.5,
+            Color::from(0.9, 0.9, 0.9),
+        )
+        .with_anisotropy(0.7),
+    ));
+
+    let mut scene = Scene::from(world);
+
+    scene.push_light(Box::new(DirectionalLight::from(
+        Vec3::from(-1.0, 0.5, -1.5),
+        Color::from(2.0, 1.7, 1.3),
+        2.0,
+    )));
+
+    scene
+}
+
+/// Three rows of principled spheres: gold going from dielectric to metal, red
+/// plastic going from smooth to rough, and then sheen, clear coat,
+/// transmission and anisotropy on their own
+fn principled_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let mut features = vec![];
+
+    let mut cloth = PrincipledParams::new();
+    cloth.base_color = Color::from(0.5, 0.1, 0.3);
+    cloth.roughness = 1.0;
+    cloth.sheen = 1.0;
+    features.push(cloth);
+
+    let mut car_paint = PrincipledParams::new();
+    car_paint.base_color = Color::from(0.05, 0.1, 0.5);
+    car_paint.roughness = 0.6;
+    car_paint.clearcoat = 1.0;
+    features.push(car_paint);
+
+    let mut glass = PrincipledParams::new();
+    glass.base_color = Color::from(0.9, 1.0, 0.9);
+    glass.roughness = 0.0;
+    glass.transmission = 1.0;
+    features.push(glass);
+
+    let mut frosted = glass;
+    frosted.roughness = 0.4;
+    features.push(frosted);
+
+    let mut brushed = PrincipledParams::new();
+    brushed.base_color = Color::from(0.9, 0.9, 0.9);
+    brushed.metallic = 1.0;
+    brushed.roughness = 0.4;
+    brushed.anisotropic = 1.0;
+    features.push(brushed);
+
+    for (i, feature) in features.iter().enumerate() {
+        let t = i as f64 / 4.0;
+        let z = -2.2 + 1.1 * i as f64;
 
-        let material = rec.material;
+        let mut metal = PrincipledParams::new();
+        metal.base_color = Color::from(1.0, 0.75, 0.3);
+        metal.roughness = 0.3;
+        metal.metallic = t;
 
-        if material.scatter(ray, &mut rec, &mut attenuation, &mut scattered) {
-            return attenuation * ray_color(&scattered, world, depth - 1);
+        let mut plastic = PrincipledParams::new();
+        plastic.base_color = Color::from(0.7, 0.05, 0.05);
+        plastic.roughness = t;
+
+        // Each row floats higher than the one in front so none are hidden
+        for (x, y, params) in [
+            (2.0, 0.4, metal),
+            (0.0, 1.2, plastic),
+            (-2.0, 2.0, *feature),
+        ]
+        .iter()
+        {
+            world.push(Box::new(Sphere::from(
+                Point3::from(*x, *y, z),
+                0.4,
+                Arc::new(Principled::from(*params)),
+            )));
         }
+    }
+
+    world
+}
+
+/// Coatings over different bases: car paint with a clear coat over rough
+/// metal, varnished wood, and the same white base under coats of increasing
+/// thickness and roughness
+fn coated_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let mut flake = PrincipledParams::new();
+    flake.base_color = Color::from(0.7, 0.05, 0.05);
+    flake.metallic = 1.0;
+    flake.roughness = 0.5;
+
+    let white: Arc<dyn Material> = Arc::new(Lambertian::from(Color::from(0.9, 0.9, 0.9)));
+    let amber = Medium::tinted(Color::from(0.8, 0.5, 0.2), 1.0);
+
+    let materials: Vec<Arc<dyn Material>> = vec![
+        Arc::new(Coated::from(Arc::new(Principled::from(flake)), 1.5, 0.0)),
+        Arc::new(
+            Coated::from(
+                Arc::new(Lambertian::from(Color::from(0.5, 0.3, 0.15))),
+                1.5,
+                0.05,
+            )
+            .with_medium(amber, 0.5),
+        ),
+        Arc::new(Coated::from(white.clone(), 1.5, 0.0).with_medium(amber, 0.2)),
+        Arc::new(Coated::from(white.clone(), 1.5, 0.0).with_medium(amber, 1.0)),
+        Arc::new(Coated::from(white, 1.5, 0.3).with_medium(amber, 1.0)),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.5, -2.4 + 1.2 * i as f64),
+            0.5,
+            material,
+        )));
+    }
+
+    world
+}
+
+/// Balls of crown glass, flint glass and diamond in front of black and white
+/// bars, whose edges pick up colored fringes when rendered with --spectral
+fn dispersion_scene() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let white: Arc<dyn Material> = Arc::new(Lambertian::from(Color::from(0.9, 0.9, 0.9)));
+    let black: Arc<dyn Material> = Arc::new(Lambertian::from(Color::from(0.02, 0.02, 0.02)));
+    for k in -12..12 {
+        let z = 0.3 * k as f64;
+        let material = if k % 2 == 0 { &white } else { &black };
+
+        world.push(Box::new(Cuboid::from(
+            Point3::from(-4.0, 0.0, z),
+            Point3::from(-3.8, 3.0, z + 0.3),
+            material.clone(),
+        )));
+    }
+
+    // Diamond is well described by Cauchy's simpler formula
+    let diamond = Dispersion::Cauchy {
+        a: 2.378,
+        b: 0.0122,
+    };
+
+    let materials: Vec<Arc<dyn Material>> = vec![
+        Arc::new(Dielectric::from(1.5).with_dispersion(Dispersion::bk7())),
+        Arc::new(Dielectric::from(1.5).with_dispersion(Dispersion::dense_flint())),
+        Arc::new(RoughDielectric::from(1.5, 0.05).with_dispersion(diamond)),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.8, -2.0 + 2.0 * i as f64),
+            0.8,
+            material,
+        )));
+    }
+
+    world
+}
+
+/// A tiled floor with bump mapped grout, and balls of hammered metal, rippled
+/// glass and either orange peel paint or the normal map in the given PPM or
+/// PFM image
+fn bumps_scene(normal_map_path: Option<&str>) -> HittableList {
+    let mut world = HittableList::new();
+
+    let tiles: Arc<dyn Texture> = Arc::new(Checker::from(
+        Color::from(1.0, 1.0, 1.0),
+        Color::new(),
+        20.0,
+    ));
+    world.push(Box::new(Cuboid::from(
+        Point3::from(-10.0, -1.0, -10.0),
+        Point3::from(10.0, 0.0, 10.0),
+        Arc::new(Perturbed::from(
+            Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+            Detail::BumpMap {
+                height: tiles,
+                strength: 0.002,
+            },
+        )),
+    )));
+
+    let noise: Arc<dyn Texture> = Arc::new(NoiseTexture::from(4.0));
+    let bumps = |strength: f64| Detail::BumpMap {
+        height: noise.clone(),
+        strength,
+    };
+
+    let paint: Arc<dyn Material> = Arc::new(Lambertian::from(Color::from(0.8, 0.3, 0.1)));
+    let detail = match normal_map_path {
+        Some(path) => Detail::NormalMap(Arc::new(
+            ImageTexture::load(Path::new(path)).expect("Failed to load normal map"),
+        )),
+        None => bumps(0.01),
+    };
+
+    let materials: Vec<Arc<dyn Material>> = vec![
+        Arc::new(Perturbed::from(
+            Arc::new(Metal::from(Color::from(0.8, 0.8, 0.85), 0.0)),
+            bumps(0.02),
+        )),
+        Arc::new(Perturbed::from(paint, detail)),
+        Arc::new(Perturbed::from(
+            Arc::new(Dielectric::from(1.5)),
+            bumps(0.03),
+        )),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.7, -1.6 + 1.6 * i as f64),
+            0.7,
+            material,
+        )));
+    }
+
+    world
+}
+
+/// A flat rectangular card facing +X, with texture coordinates running from
+/// its bottom corner at `min` to its top one at `max`
+fn card(min: Point3, max: Point3, material: Arc<dyn Material>) -> TriangleMesh {
+    let data = MeshData {
+        positions: vec![
+            Point3::from(min.x(), min.y(), min.z()),
+            Point3::from(min.x(), min.y(), max.z()),
+            Point3::from(max.x(), max.y(), max.z()),
+            Point3::from(max.x(), max.y(), min.z()),
+        ],
+        colors: None,
+        uvs: Some(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]),
+        triangles: vec![[0, 1, 2], [0, 2, 3]],
+    };
+
+    TriangleMesh::from(data, material)
+}
+
+/// A lattice cut out of a card in front of a metal ball, a ball eaten away
+/// by noise, and a card that lets half the light through its grey squares,
+/// or that's cut out by the opacity in the given PPM or PFM image
+fn cutout_scene(mask_path: Option<&str>) -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let green: Arc<dyn Material> = Arc::new(Lambertian::from(Color::from(0.2, 0.6, 0.2)));
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(-1.0, 0.7, -1.8),
+        0.7,
+        Arc::new(Metal::from(Color::from(0.8, 0.3, 0.3), 0.1)),
+    )));
+    world.push(Box::new(Cutout::from(
+        Box::new(card(
+            Point3::from(1.0, 0.0, -2.8),
+            Point3::from(1.0, 1.8, -0.8),
+            green.clone(),
+        )),
+        Arc::new(Checker::from(Color::from(1.0, 1.0, 1.0), Color::new(), 8.0)),
+        AlphaTest::Threshold(0.5),
+    )));
+
+    world.push(Box::new(Cutout::from(
+        Box::new(Sphere::from(
+            Point3::from(0.0, 0.7, 0.2),
+            0.7,
+            Arc::new(Lambertian::from(Color::from(0.8, 0.6, 0.2))),
+        )),
+        Arc::new(NoiseTexture::from(3.0)),
+        AlphaTest::Threshold(0.25),
+    )));
+
+    let (mask, test): (Arc<dyn Texture>, AlphaTest) = match mask_path {
+        Some(path) => (
+            Arc::new(ImageTexture::load(Path::new(path)).expect("Failed to load mask")),
+            AlphaTest::Threshold(0.5),
+        ),
+        None => (
+            Arc::new(Checker::from(
+                Color::from(1.0, 1.0, 1.0),
+                Color::from(0.5, 0.5, 0.5),
+                4.0,
+            )),
+            AlphaTest::Stochastic,
+        ),
+    };
+    world.push(Box::new(Cutout::from(
+        Box::new(card(
+            Point3::from(0.5, 0.0, 1.2),
+            Point3::from(0.5, 1.8, 3.0),
+            green,
+        )),
+        mask,
+        test,
+    )));
+
+    world
+}
+
+/// Balls lit by a low sun, a warm point light, and a spot light throwing a
+/// checkered cookie, or the given PPM or PFM image, on the ground
+fn lights_scene(cookie_path: Option<&str>) -> Scene {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let materials: Vec<Arc<dyn Material>> = vec![
+        Arc::new(Lambertian::from(Color::from(0.8, 0.8, 0.8))),
+        Arc::new(Metal::from(Color::from(0.8, 0.6, 0.4), 0.3)),
+        Arc::new(Dielectric::from(1.5)),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.6, -2.0 + 2.0 * i as f64),
+            0.6,
+            material,
+        )));
+    }
+
+    let mut scene = Scene::from(world);
+
+    scene.push_light(Box::new(DirectionalLight::from(
+        Vec3::from(-1.0, 0.5, -1.5),
+        Color::from(2.0, 1.7, 1.3),
+        2.0,
+    )));
+
+    scene.push_light(Box::new(PointLight::from(
+        Point3::from(1.5, 1.5, 1.0),
+        Color::from(3.0, 1.5, 0.5),
+    )));
+
+    let cookie: Arc<dyn Texture> = match cookie_path {
+        Some(path) => Arc::new(ImageTexture::load(Path::new(path)).expect("Failed to load cookie")),
+        None => Arc::new(Checker::from(Color::from(1.0, 1.0, 1.0), Color::new(), 6.0)),
+    };
+    scene.push_light(Box::new(
+        SpotLight::from(
+            Point3::from(3.0, 4.0, 0.0),
+            Point3::from(2.0, 0.0, 0.0),
+            Color::from(40.0, 40.0, 50.0),
+            15.0,
+            20.0,
+        )
+        .with_cookie(cookie),
+    ));
+
+    scene
+}
+
+/// Metal spheres going from smooth to rough under a low sun, next to a small,
+/// very bright glowing ball. Glowing objects aren't sampled like lights, so
+/// light from the ball bouncing off the floor and the metal is only found by
+/// chance and comes out as fireflies unless it's clamped. Sunlight reflected
+/// off the metal onto the floor is never found at all unless the metal is
+/// regularized, which lets the sun be sampled from it.
+fn fireflies_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    for i in 0..3 {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.6, -2.0 + 2.0 * i as f64),
+            0.6,
+            Arc::new(Metal::from(Color::from(0.8, 0.8, 0.85), 0.1 * i as f64)),
+        )));
+    }
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(-1.5, 1.6, 0.0),
+        0.1,
+        Arc::new(DiffuseLight::from(Color::from(200.0, 180.0, 150.0))),
+    )));
+
+    let mut scene = Scene::from(world);
+
+    scene.push_light(Box::new(DirectionalLight::from(
+        Vec3::from(-1.0, 0.5, -1.5),
+        Color::from(2.0, 1.7, 1.3),
+        2.0,
+    )));
+
+    scene
+}
+
+/// Balls of skin, wax, marble and jade, and optionally a mesh of wax from
+/// the given PLY or STL file
+fn subsurface_scene(mesh_path: Option<&str>) -> HittableList {
+    let mut world = HittableList::new();
+
+    world.push(Box::new(Sphere::from(
+        Point3::from(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))),
+    )));
+
+    let skin = Subsurface::from(
+        Color::from(0.99, 0.93, 0.88),
+        Color::from(0.2, 0.08, 0.05),
+        1.4,
+        0.4,
+    );
+    let wax = Subsurface::from(
+        Color::from(0.98, 0.95, 0.8),
+        Color::from(0.15, 0.15, 0.15),
+        1.45,
+        0.2,
+    );
+    let marble = Subsurface::from(
+        Color::from(0.99, 0.99, 0.98),
+        Color::from(0.1, 0.1, 0.1),
+        1.5,
+        0.05,
+    );
+    let jade = Subsurface::from(
+        Color::from(0.7, 0.95, 0.75),
+        Color::from(0.3, 0.2, 0.3),
+        1.6,
+        0.1,
+    );
+
+    let materials: Vec<Arc<dyn Material>> = vec![
+        Arc::new(skin),
+        Arc::new(wax),
+        Arc::new(marble),
+        Arc::new(jade),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.push(Box::new(Sphere::from(
+            Point3::from(0.0, 0.6, -3.3 + 2.2 * i as f64),
+            0.6,
+            material,
+        )));
+    }
+
+    if let Some(path) = mesh_path {
+        let mut data = MeshData::load(Path::new(path)).expect("Failed to load mesh");
+        data.fit(Point3::from(-2.5, 1.0, 0.0), 2.0);
 
-        return Color::new();
+        world.push(Box::new(TriangleMesh::from(
+            data,
+            Arc::new(Subsurface::from(
+                Color::from(0.98, 0.95, 0.8),
+                Color::from(0.15, 0.15, 0.15),
+                1.45,
+                0.2,
+            )),
+        )));
     }
 
-    let unit_dir: Vec3 = ray.direction().unit_vector();
+    world
+}
 
-    let t = 0.5 * (unit_dir.y() + 1.0);
+/// Remove a `--name=value` option from the arguments, returning its value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let index = args.iter().position(|arg| arg.starts_with(&prefix))?;
 
-    (1.0 - t) * Color::from(1.0, 1.0, 1.0) + t * Color::from(0.5, 0.7, 1.0)
+    Some(args.remove(index)[prefix.len()..].to_string())
 }
 
 fn main() {
-    let samples_per_pixel: u32; //: usize = 100;
-    let image_width: u32; //: usize = 384;
-    let image_height: u32; //: usize = ((IMAGE_WIDTH as f64) / ASPECT_RATIO) as usize;
+    let samples_per_pixel: u32;
+    let image_width: u32;
+
+    let mut args = std::env::args().skip(1).collect::<Vec<String>>();
 
-    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    // Passing --spectral anywhere renders with wavelengths instead of RGB
+    let spectral = args.iter().any(|arg| arg == "--spectral");
+    args.retain(|arg| arg != "--spectral");
+
+    // --env=<path> lights the scene with an HDR environment map instead of
+    // the sky gradient, turned by --env-rotation degrees and brightened by
+    // --env-scale
+    let env_path = take_option(&mut args, "--env");
+    let env_rotation = take_option(&mut args, "--env-rotation").map_or(0.0, |r| r.parse().unwrap());
+    let env_scale = take_option(&mut args, "--env-scale").map_or(1.0, |s| s.parse().unwrap());
+
+    // --sky=<elevation> lights the scene with a daylight sky and the sun that
+    // many degrees up, --sky-azimuth degrees round from +X towards +Z, through
+    // air with a turbidity of --sky-turbidity
+    let sky_elevation = take_option(&mut args, "--sky").map(|e| e.parse::<f64>().unwrap());
+    let sky_azimuth = take_option(&mut args, "--sky-azimuth").map(|a| a.parse::<f64>().unwrap());
+    let sky_turbidity =
+        take_option(&mut args, "--sky-turbidity").map(|t| t.parse::<f64>().unwrap());
+    if sky_elevation.is_none() && (sky_azimuth.is_some() || sky_turbidity.is_some()) {
+        panic!("--sky-azimuth and --sky-turbidity need --sky=<elevation>");
+    }
+
+    // --integrator=<name> picks how light is worked out: path (the
+    // default), bdpt, sppm, mlt, whitted or ao, which looks --ao-radius
+    // units around, or one of the debug views normals, uvs, depth,
+    // barycentrics or heatmap
+    let integrator_name = take_option(&mut args, "--integrator");
+    let ao_radius = take_option(&mut args, "--ao-radius").map_or(1.0, |r| r.parse().unwrap());
+
+    // --max-diffuse, --max-specular and --max-transmission limit how many
+    // bounces of each kind the path tracer follows, for mlt too
+    let mut depth_limits = DepthLimits::new();
+    if let Some(limit) = take_option(&mut args, "--max-diffuse") {
+        depth_limits.diffuse = limit.parse().unwrap();
+    }
+    if let Some(limit) = take_option(&mut args, "--max-specular") {
+        depth_limits.specular = limit.parse().unwrap();
+    }
+    if let Some(limit) = take_option(&mut args, "--max-transmission") {
+        depth_limits.transmission = limit.parse().unwrap();
+    }
+
+    // --clamp-indirect caps how bright light from more than one bounce can
+    // be, and --regularize how rough surfaces are made after the first
+    // non-specular bounce, trading a little bias for fewer fireflies
+    let mut path_tracer = PathTracer::from(depth_limits);
+    if let Some(max) = take_option(&mut args, "--clamp-indirect") {
+        path_tracer = path_tracer.with_indirect_clamp(max.parse().unwrap());
+    }
+    if let Some(roughness) = take_option(&mut args, "--regularize") {
+        path_tracer = path_tracer.with_regularization(roughness.parse().unwrap());
+    }
 
-    if args.len() != 0 {
+    // --photons sets how many photons sppm shoots each pass, gathering them
+    // from --photon-radius units around to begin with and shrinking it at a
+    // rate set by --photon-alpha
+    let mut photon_settings = PhotonSettings::new();
+    if let Some(photons) = take_option(&mut args, "--photons") {
+        photon_settings.photons = Some(photons.parse().unwrap());
+    }
+    if let Some(radius) = take_option(&mut args, "--photon-radius") {
+        photon_settings.radius = radius.parse().unwrap();
+    }
+    if let Some(alpha) = take_option(&mut args, "--photon-alpha") {
+        photon_settings.alpha = alpha.parse().unwrap();
+    }
+
+    // --mlt-bootstrap sets how many paths mlt traces up front, --mlt-sigma
+    // how far small steps nudge each sample and --mlt-large-step the chance
+    // of a step picking a whole new path
+    let mut mlt_settings = MetropolisSettings::new();
+    if let Some(bootstrap) = take_option(&mut args, "--mlt-bootstrap") {
+        mlt_settings.bootstrap = bootstrap.parse().unwrap();
+    }
+    if let Some(sigma) = take_option(&mut args, "--mlt-sigma") {
+        mlt_settings.sigma = sigma.parse().unwrap();
+    }
+    if let Some(large_step) = take_option(&mut args, "--mlt-large-step") {
+        mlt_settings.large_step = large_step.parse().unwrap();
+    }
+
+    let integrator: Box<dyn Integrator> = match integrator_name.as_deref().unwrap_or("path") {
+        "path" => Box::new(path_tracer),
+        "bdpt" => Box::new(Bdpt::from(BDPT_MAX_DEPTH)),
+        "sppm" => Box::new(PhotonMapper::from(photon_settings)),
+        "mlt" => Box::new(Metropolis::from(path_tracer, mlt_settings)),
+        "whitted" => Box::new(Whitted::from(MAX_DEPTH)),
+        "ao" => Box::new(AmbientOcclusion::from(ao_radius)),
+        "normals" => Box::new(DebugIntegrator::from(DebugView::Normals)),
+        "uvs" => Box::new(DebugIntegrator::from(DebugView::Uvs)),
+        "depth" => Box::new(DebugIntegrator::from(DebugView::Depth)),
+        "barycentrics" => Box::new(DebugIntegrator::from(DebugView::Barycentrics)),
+        "heatmap" => Box::new(DebugIntegrator::from(DebugView::Heatmap)),
+        name => panic!("Unknown integrator: {}", name),
+    };
+
+    if !args.is_empty() {
         samples_per_pixel = args[0].parse().unwrap();
         image_width = args[1].parse().unwrap();
     } else {
         samples_per_pixel = 100;
         image_width = 384;
     }
-    image_height = ((image_width as f64) / ASPECT_RATIO) as u32;
-
-    // file header
-    println!("P3"); // specifies that colors are in ASCII
-    println!("{} {}", image_width, image_height); // columns and rows
-    println!("255"); // max color
+    let image_height = ((image_width as f64) / ASPECT_RATIO) as u32;
 
-    let mut rng = rand::thread_rng();
+    // The optional third argument picks the scene to render, and any after
+    // that are passed on to the scene
+    let scene_name = args.get(2).map(String::as_str).unwrap_or("random");
 
     // General steps for race tracing:
     // 1. Calculate ray from eye to pixel
     // 2. Determine which objects the ray intersects
     // 3. Compute a color for that intersection point
-    let world = random_scene();
+    let mut scene = match scene_name {
+        "random" => Scene::from(random_scene()),
+        "smoke" => {
+            // Light from far away has to start outside the fog, or it would
+            // skip being dimmed by it
+            let mut scene = Scene::from(smoke_scene());
+            scene.radius = 50.0;
+            scene
+        }
+        "lights" => lights_scene(args.get(3).map(String::as_str)),
+        "bumps" => Scene::from(bumps_scene(args.get(3).map(String::as_str))),
+        "subsurface" => Scene::from(subsurface_scene(args.get(3).map(String::as_str))),
+        "tinted" => Scene::from(tinted_scene()),
+        "water" => water_scene(),
+        "fireflies" => fireflies_scene(),
+        "coated" => Scene::from(coated_scene()),
+        "csg" => Scene::from(csg_scene()),
+        "cutout" => Scene::from(cutout_scene(args.get(3).map(String::as_str))),
+        "dispersion" => Scene::from(dispersion_scene()),
+        "frosted" => Scene::from(frosted_scene()),
+        "metals" => Scene::from(metals_scene()),
+        "principled" => Scene::from(principled_scene()),
+        "mesh" => Scene::from(mesh_scene(args.get(3).map(String::as_str))),
+        "sdf" => Scene::from(sdf_scene()),
+        "volume" => Scene::from(volume_scene(args.get(3).map(String::as_str))),
+        _ => panic!("Unknown scene: {}", scene_name),
+    };
+
+    if let Some(path) = env_path {
+        scene.environment = Box::new(
+            EnvironmentMap::load(Path::new(&path), env_rotation, env_scale)
+                .expect("Failed to load environment map"),
+        );
+    }
+
+    if let Some(elevation) = sky_elevation {
+        let sky = PhysicalSky::from(
+            elevation,
+            sky_azimuth.unwrap_or(0.0),
+            sky_turbidity.unwrap_or(3.0),
+        );
+        scene.push_light(Box::new(sky.sun()));
+        scene.environment = Box::new(sky);
+    }
 
     let cam = Camera::from(
         Point3::from(13.0, 2.0, 3.0),
@@ -168,24 +1281,7 @@ fn main() {
         10.0,
     );
 
-    for j in (0..image_height).rev() {
-        eprintln!("Scan lines left: {}", j);
-        for i in 0..image_width {
-            let mut color = Color::new();
-
-            // Sampling loop. We take a bunch of sample with slight shifts in
-            // location (within 1.0 units from the current position), add them
-            // together, and then take an average. This creates a smoother look
-            // on edges
-            for _s in 0..SAMPLES_PER_PIXEL {
-                let u = ((i as f64 + rng.gen::<f64>()) / (image_width - 1) as f64) as f64;
-                let v = ((j as f64 + rng.gen::<f64>()) / (image_height - 1) as f64) as f64;
-
-                let ray = cam.get_ray(u, v);
-                color += ray_color(&ray, &world, MAX_DEPTH);
-            }
-
-            color.print_color(samples_per_pixel);
-        }
-    }
+    let mut film = Film::from(image_width, image_height);
+    integrator.render(&scene, &cam, &mut film, samples_per_pixel, spectral);
+    film.write(samples_per_pixel);
 }
@@ -1,80 +1,830 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 
-use crate::{Color, HitRecord, Hittable, Point3, Ray, Vec3};
+use crate::medium::Medium;
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::sampler;
+use crate::spectrum::Dispersion;
+use crate::{Color, HitRecord, Ray, Vec3};
+
+/// Complex index of refraction (eta, then k) of some common metals, for the
+/// red, green and blue channels
+const METALS: [(&str, [f64; 3], [f64; 3]); 5] = [
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminium", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("aluminum", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+];
+
+/// The result of a material scattering a ray
+#[derive(Debug, Clone)]
+pub struct ScatterRecord {
+    /// What the light arriving along the scattered ray is multiplied by. This
+    /// already accounts for the probability of picking the scattered direction,
+    /// so it's `eval / pdf` for non-specular scattering.
+    pub attenuation: Color,
+    pub scattered: Ray,
+    /// Whether the direction came from a perfectly sharp reflection or
+    /// refraction, or from a random walk through layers, neither of which
+    /// `eval` and `pdf` can describe
+    pub specular: bool,
+    /// Whether the direction depended on the wavelength of the ray, so only
+    /// the hero wavelength of a spectral ray can follow it
+    pub dispersed: bool,
+}
+
+impl ScatterRecord {
+    pub fn new() -> Self {
+        Self {
+            attenuation: Color::new(),
+            scattered: Ray::new(),
+            specular: false,
+            dispersed: false,
+        }
+    }
+}
+
+/// How light interacts with a surface (or the inside of a volume)
+pub trait Material: Send + Sync {
+    /// Pick a direction for the ray to scatter in. Returns false if the ray is
+    /// absorbed.
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool;
+
+    /// The BSDF for light scattered from `scattered` into `ray_in`, times the
+    /// cosine of the angle between `scattered` and the normal. Specular
+    /// materials have no finite value, so they return black.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        Color::new()
+    }
+
+    /// The probability density of `scatter` picking the direction of
+    /// `scattered`, with respect to solid angle
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// The light emitted by the material at the hit point
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new()
+    }
+
+    /// What fills the inside of objects made of the material, for those that
+    /// let light through. Rays refracted inside travel through it until they
+    /// refract back out.
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    /// Whether the material is on a surface, rather than spread through a
+    /// volume where light scatters the same whichever way it arrives from
+    fn on_surface(&self) -> bool {
+        true
+    }
+
+    /// How rough the surface is, from 0 for a mirror to 1 for one that
+    /// scatters light evenly every which way. Integrators that can't handle
+    /// sharp reflections use it to follow rays on past smoother surfaces.
+    fn roughness(&self) -> f64 {
+        1.0
+    }
+
+    /// Index of refraction of objects made of the material, for those that
+    /// refract light going into them
+    fn ior(&self) -> Option<f64> {
+        None
+    }
+
+    /// Which object light goes by where two that let it through overlap:
+    /// the one with the higher priority fills the overlap, and the other's
+    /// surface is ignored inside it. This lets water overlap the glass it's
+    /// in a little, so there's no gap or shared surface between them.
+    fn priority(&self) -> u32 {
+        0
+    }
+}
+
+pub struct Lambertian {
+    albedo: Color,
+}
+
+impl Lambertian {
+    pub fn from(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut scatter_dir = rec.normal + Vec3::random_unit_vector();
+
+        // The random vector can land right opposite the normal
+        if scatter_dir.length_squared() < 1e-12 {
+            scatter_dir = rec.normal;
+        }
+
+        srec.scattered = Ray::from(rec.p, scatter_dir);
+        srec.attenuation = self.albedo * rec.color;
+        srec.specular = false;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Material {
-    Metal { albedo: Color, fuzz: f64 },
-    Lambertian { albedo: Color },
-    Dielectric { ref_idx: f64 },
+        true
+    }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let cos = rec.normal.dot(scattered.direction().unit_vector()).max(0.0);
+
+        (cos / PI) * self.albedo * rec.color
+    }
+
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        // Offsetting a random unit vector along the normal gives a cosine
+        // weighted distribution
+        rec.normal.dot(scattered.direction().unit_vector()).max(0.0) / PI
+    }
+}
+
+pub struct Metal {
+    albedo: Color,
+    fuzz: f64,
 }
 
-impl Material {
-    pub fn scatter(
-        &self,
-        ray_in: &Ray,
-        rec: &mut HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        match &self {
-            Material::Metal { albedo, fuzz } => {
-                let real_fuzz = if *fuzz >= 1.0 { 1.0 } else { *fuzz };
+impl Metal {
+    pub fn from(albedo: Color, fuzz: f64) -> Self {
+        Self {
+            albedo,
+            fuzz: fuzz.min(1.0),
+        }
+    }
+
+    /// A GGX lobe standing in for the fuzzy reflection when the surface is
+    /// smoother than `min_roughness`, so it can be evaluated for direct
+    /// lighting like the other metals. Rougher metal is left as it is.
+    fn regularized(&self, min_roughness: f64) -> Option<TrowbridgeReitz> {
+        if self.fuzz < min_roughness {
+            Some(TrowbridgeReitz::from_roughness(min_roughness))
+        } else {
+            None
+        }
+    }
+}
 
+impl Material for Metal {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let distribution = match self.regularized(rec.min_roughness) {
+            Some(distribution) => distribution,
+            None => {
                 let reflected = ray_in.direction().unit_vector().reflect(rec.normal);
-                *scattered =
-                    Ray::from(rec.p, reflected + real_fuzz * Vec3::random_in_unit_sphere());
-                *attenuation = *albedo;
+                srec.scattered =
+                    Ray::from(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
+                srec.attenuation = self.albedo;
+                srec.specular = true;
 
-                scattered.direction().dot(rec.normal) > 0.0
+                return srec.scattered.direction().dot(rec.normal) > 0.0;
             }
-            Material::Lambertian { albedo } => {
-                let scatter_dir = rec.normal + Vec3::random_unit_vector();
-                *scattered = Ray::from(rec.p, scatter_dir);
-                *attenuation = *albedo;
+        };
 
-                true
+        let mut rng = sampler::rng();
+
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.world_to_local(-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let wm = distribution.sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+        let wi = microfacet::reflect(wo, wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+        srec.attenuation = self.albedo * (distribution.g(wo, wi) / distribution.g1(wo));
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        match self.regularized(rec.min_roughness) {
+            Some(distribution) if wo.z() > 0.0 && wi.z() > 0.0 => {
+                let wm = (wo + wi).unit_vector();
+                distribution.reflection(wo, wi, wm) * self.albedo
             }
-            Material::Dielectric { ref_idx } => {
-                let mut rng = rand::thread_rng();
+            _ => Color::new(),
+        }
+    }
 
-                *attenuation = Color::from(1.0, 1.0, 1.0);
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        match self.regularized(rec.min_roughness) {
+            Some(distribution) if wo.z() > 0.0 && wi.z() > 0.0 => {
+                distribution.reflection_pdf(wo, (wo + wi).unit_vector())
+            }
+            _ => 0.0,
+        }
+    }
 
-                // calculate if the light should refract or not
-                let etai_over_etat = if rec.front_face {
-                    1.0 / *ref_idx
-                } else {
-                    *ref_idx
-                };
+    fn roughness(&self) -> f64 {
+        self.fuzz
+    }
+}
 
-                let unit_dir = ray_in.direction().unit_vector();
+/// A rough metal, using the GGX microfacet model with the exact Fresnel
+/// reflectance of a conductor with a complex index of refraction `eta + ik`
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
 
-                let cos_theta = (-unit_dir).dot(rec.normal).min(1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+impl Conductor {
+    pub fn from(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+        }
+    }
 
-                if etai_over_etat * sin_theta > 1.0 {
-                    // Reflect
-                    let reflected = unit_dir.reflect(rec.normal);
-                    *scattered = Ray::from(rec.p, reflected);
-                    return true;
-                }
+    /// A metal from the built in table: gold, copper, aluminium or silver
+    pub fn named(name: &str, roughness: f64) -> Option<Self> {
+        METALS
+            .iter()
+            .find(|(metal, _, _)| *metal == name)
+            .map(|(_, eta, k)| {
+                Self::from(
+                    Color::from(eta[0], eta[1], eta[2]),
+                    Color::from(k[0], k[1], k[2]),
+                    roughness,
+                )
+            })
+    }
+}
 
-                let reflect_prob = schlick(cos_theta, etai_over_etat);
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let mut rng = sampler::rng();
 
-                if rng.gen::<f64>() < reflect_prob {
-                    let reflected = unit_dir.reflect(rec.normal);
-                    *scattered = Ray::from(rec.p, reflected);
-                    return true;
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.world_to_local(-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if distribution.is_smooth() {
+            let wi = Vec3::from(-wo.x(), -wo.y(), wo.z());
+            srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+            srec.attenuation = microfacet::fresnel_conductor(wo.z(), self.eta, self.k);
+            srec.specular = true;
+
+            return true;
+        }
+
+        // Only sample normals that can be seen from the outgoing direction,
+        // which wastes far fewer samples than sampling the whole distribution
+        let wm = distribution.sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+        let wi = microfacet::reflect(wo, wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+        srec.attenuation = microfacet::fresnel_conductor(wo.dot(wm), self.eta, self.k)
+            * (distribution.g(wo, wi) / distribution.g1(wo));
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if distribution.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new();
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let f = microfacet::fresnel_conductor(wo.dot(wm), self.eta, self.k);
+
+        distribution.reflection(wo, wi, wm) * f
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if distribution.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).unit_vector();
+
+        distribution.reflection_pdf(wo, wm)
+    }
+
+    fn roughness(&self) -> f64 {
+        self.distribution.roughness()
+    }
+}
+
+pub struct Dielectric {
+    ref_idx: f64,
+    medium: Option<Medium>,
+    dispersion: Option<Dispersion>,
+    priority: u32,
+}
+
+impl Dielectric {
+    pub fn from(ref_idx: f64) -> Self {
+        Self {
+            ref_idx,
+            medium: None,
+            dispersion: None,
+            priority: 0,
+        }
+    }
+
+    /// Take over from lower priority objects where they overlap
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Fill the inside with an absorbing medium, tinting it by thickness
+    pub fn with_medium(mut self, medium: Medium) -> Self {
+        self.medium = Some(medium);
+        self
+    }
+
+    /// Make the index of refraction depend on wavelength in spectral mode.
+    /// RGB rays use the index at the d line.
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ref_idx = dispersion.d_line_ior();
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    /// Rough glass standing in for this once the surface has to be at least
+    /// `min_roughness` rough
+    fn regularized(&self, min_roughness: f64) -> Option<RoughDielectric> {
+        if min_roughness <= 0.0 {
+            return None;
+        }
+
+        let rough = RoughDielectric::from(self.ref_idx, min_roughness);
+        Some(match self.dispersion {
+            Some(dispersion) => rough.with_dispersion(dispersion),
+            None => rough,
+        })
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        if let Some(rough) = self.regularized(rec.min_roughness) {
+            return rough.scatter(ray_in, rec, srec);
+        }
+
+        let mut rng = sampler::rng();
+
+        srec.attenuation = Color::from(1.0, 1.0, 1.0);
+        srec.specular = true;
+        srec.dispersed = self.dispersion.is_some();
+
+        let ref_idx = ior(self.ref_idx, self.dispersion, ray_in);
+
+        // calculate if the light should refract or not
+        let etai_over_etat = if rec.front_face {
+            rec.outside_ior / ref_idx
+        } else {
+            ref_idx / rec.outside_ior
+        };
+
+        let unit_dir = ray_in.direction().unit_vector();
+
+        let cos_theta = (-unit_dir).dot(rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        if etai_over_etat * sin_theta > 1.0 {
+            // Reflect
+            let reflected = unit_dir.reflect(rec.normal);
+            srec.scattered = Ray::from(rec.p, reflected);
+            return true;
+        }
+
+        let reflect_prob = schlick(cos_theta, etai_over_etat);
+
+        if rng.gen::<f64>() < reflect_prob {
+            let reflected = unit_dir.reflect(rec.normal);
+            srec.scattered = Ray::from(rec.p, reflected);
+            return true;
+        }
+
+        // Refract
+        let refracted = unit_dir.refract(rec.normal, etai_over_etat);
+        srec.scattered = Ray::from(rec.p, refracted);
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        match self.regularized(rec.min_roughness) {
+            Some(rough) => rough.eval(ray_in, rec, scattered),
+            None => Color::new(),
+        }
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        match self.regularized(rec.min_roughness) {
+            Some(rough) => rough.pdf(ray_in, rec, scattered),
+            None => 0.0,
+        }
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.medium
+    }
+
+    fn roughness(&self) -> f64 {
+        0.0
+    }
+
+    fn ior(&self) -> Option<f64> {
+        Some(self.ref_idx)
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+}
+
+/// Glass with a rough surface, like frosted glass, which both reflects and
+/// transmits through GGX microfacets using the exact dielectric Fresnel term
+pub struct RoughDielectric {
+    ref_idx: f64,
+    distribution: TrowbridgeReitz,
+    medium: Option<Medium>,
+    dispersion: Option<Dispersion>,
+    priority: u32,
+}
+
+impl RoughDielectric {
+    pub fn from(ref_idx: f64, roughness: f64) -> Self {
+        Self {
+            ref_idx,
+            distribution: TrowbridgeReitz::from_roughness(roughness),
+            medium: None,
+            dispersion: None,
+            priority: 0,
+        }
+    }
+
+    /// Take over from lower priority objects where they overlap
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Fill the inside with an absorbing medium, tinting it by thickness
+    pub fn with_medium(mut self, medium: Medium) -> Self {
+        self.medium = Some(medium);
+        self
+    }
+
+    /// Make the index of refraction depend on wavelength in spectral mode.
+    /// RGB rays use the index at the d line.
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.ref_idx = dispersion.d_line_ior();
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    /// Index of refraction on the far side of the surface over the one on the
+    /// side the ray came from
+    fn relative_eta(&self, ray_in: &Ray, rec: &HitRecord) -> f64 {
+        let ref_idx = ior(self.ref_idx, self.dispersion, ray_in);
+
+        if rec.front_face {
+            ref_idx / rec.outside_ior
+        } else {
+            rec.outside_ior / ref_idx
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let mut rng = sampler::rng();
+
+        // The normal always faces the incoming ray, so `wo` is above the surface
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.world_to_local(-ray_in.direction().unit_vector());
+        let eta = self.relative_eta(ray_in, rec);
+
+        srec.attenuation = Color::from(1.0, 1.0, 1.0);
+        srec.dispersed = self.dispersion.is_some();
+
+        if distribution.is_smooth() {
+            let n = Vec3::from(0.0, 0.0, 1.0);
+            let reflect_prob = microfacet::fresnel_dielectric(wo.z(), eta);
+
+            let wi = if rng.gen::<f64>() < reflect_prob {
+                microfacet::reflect(wo, n)
+            } else {
+                match microfacet::refract(wo, n, eta) {
+                    Some(wi) => wi,
+                    None => microfacet::reflect(wo, n),
                 }
+            };
 
-                // Refract
-                let refracted = unit_dir.refract(rec.normal, etai_over_etat);
-                *scattered = Ray::from(rec.p, refracted);
+            srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+            srec.specular = true;
+
+            return true;
+        }
 
-                true
+        let wm = distribution.sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+        let reflect_prob = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+
+        // Choosing between reflection and transmission by the Fresnel term
+        // cancels it out of the weight, leaving only the shadowing
+        let wi = if rng.gen::<f64>() < reflect_prob {
+            let wi = microfacet::reflect(wo, wm);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            wi
+        } else {
+            match microfacet::refract(wo, wm, eta) {
+                Some(wi) if wi.z() < 0.0 => wi,
+                _ => return false,
             }
+        };
+
+        srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+        srec.attenuation =
+            Color::from(1.0, 1.0, 1.0) * (distribution.g(wo, wi) / distribution.g1(wo));
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        let eta = self.relative_eta(ray_in, rec);
+        if distribution.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Color::new();
+        }
+
+        let wm = match microfacet::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return Color::new(),
+        };
+
+        let f = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+        let value = if wi.z() > 0.0 {
+            distribution.reflection(wo, wi, wm) * f
+        } else {
+            distribution.transmission(wo, wi, wm, eta) * (1.0 - f)
+        };
+
+        Color::from(value, value, value)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let distribution = self.distribution.regularized(rec.min_roughness);
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        let eta = self.relative_eta(ray_in, rec);
+        if distribution.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+
+        let wm = match microfacet::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let reflect_prob = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+        if wi.z() > 0.0 {
+            reflect_prob * distribution.reflection_pdf(wo, wm)
+        } else {
+            (1.0 - reflect_prob) * distribution.transmission_pdf(wo, wi, wm, eta)
+        }
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.medium
+    }
+
+    fn roughness(&self) -> f64 {
+        self.distribution.roughness()
+    }
+
+    fn ior(&self) -> Option<f64> {
+        Some(self.ref_idx)
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+}
+
+/// Translucent materials like skin, wax and marble. Light refracts in through
+/// a rough dielectric surface and takes a random walk through the scattering
+/// medium inside, coming back out somewhere else. Only works on closed
+/// objects.
+pub struct Subsurface {
+    surface: RoughDielectric,
+}
+
+impl Subsurface {
+    /// Configured by the fraction of light surviving each scattering event
+    /// and the average distance between them, for each channel
+    pub fn from(albedo: Color, mean_free_path: Color, ref_idx: f64, roughness: f64) -> Self {
+        Self {
+            surface: RoughDielectric::from(ref_idx, roughness)
+                .with_medium(Medium::from_albedo(albedo, mean_free_path)),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.surface.scatter(ray_in, rec, srec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.surface.eval(ray_in, rec, scattered)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.surface.pdf(ray_in, rec, scattered)
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.surface.medium()
+    }
+
+    fn roughness(&self) -> f64 {
+        self.surface.roughness()
+    }
+
+    fn ior(&self) -> Option<f64> {
+        self.surface.ior()
+    }
+}
+
+/// Phase function of a participating medium, scattering uniformly in all
+/// directions
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn from(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.scattered = Ray::from(rec.p, Vec3::random_unit_vector());
+        srec.attenuation = self.albedo;
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        self.albedo / (4.0 * PI)
+    }
+
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn on_surface(&self) -> bool {
+        false
+    }
+}
+
+/// Phase function of a participating medium that scatters more light forwards
+/// than backwards, or the other way round, following Henyey and Greenstein.
+/// `g` is the average cosine of the angle light turns through, from -1 for
+/// straight back, through 0 for evenly, to 1 for straight on.
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn from(albedo: Color, g: f64) -> Self {
+        Self {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
         }
     }
+
+    /// Density of light turning through an angle with the given cosine
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let mut rng = sampler::rng();
+
+        let g = self.g;
+        let xi = rng.gen::<f64>();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+
+        // The angle is measured from the way the ray was already going
+        let frame = Onb::from_w(ray_in.direction().unit_vector());
+        let direction = frame.local_to_world(Vec3::from(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        srec.scattered = Ray::from(rec.p, direction);
+        srec.attenuation = self.albedo;
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.albedo * self.pdf(ray_in, rec, scattered)
+    }
+
+    fn pdf(&self, ray_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = ray_in
+            .direction()
+            .unit_vector()
+            .dot(scattered.direction().unit_vector());
+
+        self.phase(cos_theta)
+    }
+
+    fn on_surface(&self) -> bool {
+        false
+    }
+}
+
+/// Emits light of the given color and does not scatter
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn from(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+        false
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+/// Index of refraction for the hero wavelength of a spectral ray, or the fixed
+/// one for RGB rays and materials without dispersion
+fn ior(ref_idx: f64, dispersion: Option<Dispersion>, ray: &Ray) -> f64 {
+    match (dispersion, ray.wavelengths()) {
+        (Some(dispersion), Some(wavelengths)) => dispersion.ior(wavelengths.x()),
+        _ => ref_idx,
+    }
+}
+
+/// The outgoing and incoming directions in the local shading frame of a hit
+pub fn local_dirs(ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> (Vec3, Vec3) {
+    let frame = Onb::from_w(rec.normal);
+
+    (
+        frame.world_to_local(-ray_in.direction().unit_vector()),
+        frame.world_to_local(scattered.direction().unit_vector()),
+    )
 }
 
 /// Schlick approximation baed on the cosine and refraction index
@@ -86,3 +836,72 @@ fn schlick(cos: f64, ref_idx: f64) -> f64 {
 
     r0 + (1.0 - r0) * (1.0 - cos).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point3;
+
+    /// A hit on the XY plane, seen from above, that has to be at least
+    /// `min_roughness` rough
+    fn hit(min_roughness: f64) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.p = Point3::new();
+        rec.normal = Vec3::from(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        rec.min_roughness = min_roughness;
+        rec
+    }
+
+    /// Check that every direction `material` samples is weighted the same by
+    /// the scatter record as by its BSDF over the sampling density
+    fn assert_consistent(material: &dyn Material, rec: &HitRecord) {
+        let ray = Ray::from(Point3::from(-1.0, 0.3, 1.0), Vec3::from(1.0, -0.3, -1.0));
+
+        let mut sampled = 0;
+        for _ in 0..1000 {
+            let mut srec = ScatterRecord::new();
+            if !material.scatter(&ray, rec, &mut srec) {
+                continue;
+            }
+            assert!(!srec.specular);
+
+            let pdf = material.pdf(&ray, rec, &srec.scattered);
+            assert!(pdf > 0.0);
+
+            let weight = material.eval(&ray, rec, &srec.scattered) / pdf;
+            for axis in 0..3 {
+                let expected = srec.attenuation[axis];
+                assert!(
+                    (weight[axis] - expected).abs() < 1e-6 * expected.max(1.0),
+                    "eval / pdf gave {:?} but scatter gave {:?}",
+                    weight,
+                    srec.attenuation
+                );
+            }
+            sampled += 1;
+        }
+        assert!(sampled > 0);
+    }
+
+    #[test]
+    fn regularized_metal() {
+        let metal = Metal::from(Color::from(0.9, 0.6, 0.3), 0.05);
+        assert_consistent(&metal, &hit(0.3));
+    }
+
+    #[test]
+    fn rough_metal_is_not_regularized() {
+        let metal = Metal::from(Color::from(0.9, 0.6, 0.3), 0.5);
+        let ray = Ray::from(Point3::from(-1.0, 0.0, 1.0), Vec3::from(1.0, 0.0, -1.0));
+
+        let mut srec = ScatterRecord::new();
+        metal.scatter(&ray, &hit(0.3), &mut srec);
+        assert!(srec.specular);
+    }
+
+    #[test]
+    fn regularized_dielectric() {
+        assert_consistent(&Dielectric::from(1.5), &hit(0.3));
+    }
+}
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::material::{HenyeyGreenstein, Material};
+use crate::sampler;
+use crate::{spectrum, Color, Ray};
+
+/// What fills the inside of a closed surface, like the body of a glass object,
+/// which absorbs light following the Beer–Lambert law as it passes through,
+/// and may also scatter it like the inside of skin or wax
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    absorption: Color,
+    scattering: Color,
+    /// Average cosine of the angle light turns through when it scatters
+    anisotropy: f64,
+}
+
+/// What happens to a ray travelling through a scattering medium
+pub enum Interaction {
+    /// The ray scattered off the medium after travelling `distance`
+    Scatter { distance: f64, weight: Color },
+    /// The ray made it all the way through without scattering
+    Pass { weight: Color },
+}
+
+impl Medium {
+    /// A medium absorbing the given fraction of each channel per unit distance
+    pub fn from(absorption: Color) -> Self {
+        Self {
+            absorption,
+            scattering: Color::new(),
+            anisotropy: 0.0,
+        }
+    }
+
+    /// A medium that scatters light, given the fraction of light that
+    /// survives each scattering event and the average distance between them,
+    /// for each channel
+    pub fn from_albedo(albedo: Color, mean_free_path: Color) -> Self {
+        let extinction = Color::from(1.0, 1.0, 1.0) / mean_free_path;
+        let scattering = albedo * extinction;
+
+        Self {
+            absorption: extinction - scattering,
+            scattering,
+            anisotropy: 0.0,
+        }
+    }
+
+    /// Scatter light more forwards, for `g` up to 1, or backwards, for `g`
+    /// down to -1, rather than evenly
+    pub fn with_anisotropy(mut self, g: f64) -> Self {
+        self.anisotropy = g;
+        self
+    }
+
+    /// A medium that tints light to `color` after travelling `distance`
+    /// through it, which is easier to pick than an absorption coefficient
+    pub fn tinted(color: Color, distance: f64) -> Self {
+        let coefficient = |c: f64| -c.max(1e-6).ln() / distance;
+
+        Self::from(Color::from(
+            coefficient(color.x()),
+            coefficient(color.y()),
+            coefficient(color.z()),
+        ))
+    }
+
+    /// Fraction of the light that makes it through `distance` of the medium
+    pub fn transmittance(&self, distance: f64) -> Color {
+        beer_lambert(self.absorption + self.scattering, distance)
+    }
+
+    /// Whether light can scatter inside the medium, or only be absorbed
+    pub fn scatters(&self) -> bool {
+        self.scattering.length_squared() > 0.0
+    }
+
+    /// Which way light goes when it scatters. The weight `sample` gives
+    /// already includes how much of it scatters, so this doesn't absorb any.
+    pub fn phase_function(&self) -> HenyeyGreenstein {
+        HenyeyGreenstein::from(Color::from(1.0, 1.0, 1.0), self.anisotropy)
+    }
+
+    /// Sample how far the ray gets through the medium before it scatters, if
+    /// it does before `max_distance`. The weight makes up for how likely the
+    /// outcome was.
+    ///
+    /// Each channel has its own density, so one is picked at random to sample
+    /// the distance with, and the weight accounts for all of them having
+    /// been possible.
+    pub fn sample(&self, ray: &Ray, max_distance: f64) -> Interaction {
+        let mut rng = sampler::rng();
+
+        let scattering = spectrum::for_ray(self.scattering, ray);
+        let extinction = spectrum::for_ray(self.absorption, ray) + scattering;
+
+        let channel = rng.gen_range(0, 3);
+        let distance = if extinction[channel] > 0.0 {
+            -(1.0 - rng.gen::<f64>()).ln() / extinction[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        let average = |c: Color| (c.x() + c.y() + c.z()) / 3.0;
+
+        if distance < max_distance {
+            let transmittance = beer_lambert(extinction, distance);
+            let pdf = average(extinction * transmittance);
+
+            Interaction::Scatter {
+                distance,
+                weight: scattering * transmittance / pdf,
+            }
+        } else {
+            let transmittance = beer_lambert(extinction, max_distance);
+            let pdf = average(transmittance);
+            if pdf <= 0.0 {
+                return Interaction::Pass {
+                    weight: Color::new(),
+                };
+            }
+
+            Interaction::Pass {
+                weight: transmittance / pdf,
+            }
+        }
+    }
+}
+
+/// Fraction of light making it through `distance` of a medium with the given
+/// extinction coefficients
+fn beer_lambert(extinction: Color, distance: f64) -> Color {
+    // A channel that isn't absorbed at all stays at one, even over an
+    // infinite distance
+    let channel = |a: f64| if a > 0.0 { (-a * distance).exp() } else { 1.0 };
+
+    Color::from(
+        channel(extinction.x()),
+        channel(extinction.y()),
+        channel(extinction.z()),
+    )
+}
+
+/// An object a ray is inside of
+#[derive(Clone)]
+struct Inside {
+    material: Arc<dyn Material>,
+    medium: Option<Medium>,
+    ior: f64,
+    priority: u32,
+}
+
+/// The objects a ray is inside of, innermost last. Refracting into an object
+/// pushes it, and refracting back out removes it again, so nested objects
+/// like an ice cube in a glass of water work out. Where objects overlap, the
+/// one with the highest priority fills the overlap, or the innermost of
+/// those tied for it.
+#[derive(Clone)]
+pub struct MediumStack {
+    inside: Vec<Inside>,
+}
+
+impl MediumStack {
+    /// Outside of everything, where nothing is absorbed
+    pub fn new() -> Self {
+        Self { inside: vec![] }
+    }
+
+    /// Go into an object made of `material`. Materials that don't let light
+    /// through have no inside to go into.
+    pub fn enter(&mut self, material: &Arc<dyn Material>) {
+        if !has_inside(&**material) {
+            return;
+        }
+
+        self.inside.push(Inside {
+            material: material.clone(),
+            medium: material.medium(),
+            ior: material.ior().unwrap_or(1.0),
+            priority: material.priority(),
+        });
+    }
+
+    /// Leave the innermost object made of `material`. Objects made of the
+    /// same material can't be told apart, but it doesn't matter which one is
+    /// removed.
+    pub fn leave(&mut self, material: &Arc<dyn Material>) {
+        if let Some(i) = self.position(material) {
+            self.inside.remove(i);
+        }
+    }
+
+    /// The medium the ray is currently in, if any
+    pub fn current(&self) -> Option<&Medium> {
+        self.filling(None).and_then(|inside| inside.medium.as_ref())
+    }
+
+    /// Fraction of the light that makes it through `distance` of whatever
+    /// medium the ray is currently in
+    pub fn transmittance(&self, distance: f64) -> Color {
+        match self.current() {
+            Some(medium) => medium.transmittance(distance),
+            None => Color::from(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Whether the surface of an object made of `material`, which the ray is
+    /// going into or coming out of, is inside something with a higher
+    /// priority, so light goes straight through it
+    pub fn hides(&self, material: &Arc<dyn Material>, entering: bool) -> bool {
+        if !has_inside(&**material) {
+            return false;
+        }
+
+        match self.filling(self.around(material, entering)) {
+            Some(inside) => inside.priority > material.priority(),
+            None => false,
+        }
+    }
+
+    /// Index of refraction of whatever fills the space around an object
+    /// made of `material`, where the ray is going into or coming out of it
+    pub fn outside_ior(&self, material: &Arc<dyn Material>, entering: bool) -> f64 {
+        self.filling(self.around(material, entering))
+            .map_or(1.0, |inside| inside.ior)
+    }
+
+    /// The object to leave out to find what's around one made of `material`:
+    /// itself when the ray is coming out of it
+    fn around(&self, material: &Arc<dyn Material>, entering: bool) -> Option<usize> {
+        if entering {
+            None
+        } else {
+            self.position(material)
+        }
+    }
+
+    fn position(&self, material: &Arc<dyn Material>) -> Option<usize> {
+        self.inside
+            .iter()
+            .rposition(|inside| Arc::ptr_eq(&inside.material, material))
+    }
+
+    /// The object filling the space the ray is in, leaving out the one at
+    /// index `skip`
+    fn filling(&self, skip: Option<usize>) -> Option<&Inside> {
+        self.inside
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != skip)
+            .map(|(_, inside)| inside)
+            .max_by_key(|inside| inside.priority)
+    }
+}
+
+/// Whether light can get inside objects made of the material
+fn has_inside(material: &dyn Material) -> bool {
+    material.ior().is_some() || material.medium().is_some()
+}
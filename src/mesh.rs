@@ -0,0 +1,305 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::{ply, stl};
+use crate::{Color, HitRecord, Hittable, Material, Point3, Ray, Vec3};
+
+/// Triangles in a node below which we stop splitting the BVH
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// The raw contents of a mesh file, before it's turned into something that
+/// can be rendered
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    /// One color per vertex, if the file has them
+    pub colors: Option<Vec<Color>>,
+    /// One pair of texture coordinates per vertex, if the file has them
+    pub uvs: Option<Vec<(f64, f64)>>,
+    /// Indices into `positions` for the corners of each triangle
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    /// Load a mesh from a PLY or STL file, picked by the file extension
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("ply") => ply::parse(&bytes),
+            Some("stl") => stl::parse(&bytes),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown mesh format: {}", path.display()),
+            )),
+        }
+    }
+
+    /// Uniformly scale and move the mesh so that it's centered on `center` and
+    /// its longest side is `size` long. Meshes come in all sorts of units, so
+    /// this is the easiest way to drop one into a scene.
+    pub fn fit(&mut self, center: Point3, size: f64) {
+        let mut bounds = Aabb::empty();
+        for p in self.positions.iter() {
+            bounds.add_point(*p);
+        }
+
+        // A mesh that's all one point has no size to scale up, so it's just
+        // moved to the center
+        let extent = bounds.max - bounds.min;
+        let longest = extent.x().max(extent.y()).max(extent.z());
+        let scale = if longest > 0.0 { size / longest } else { 0.0 };
+        let offset = bounds.centroid();
+
+        for p in self.positions.iter_mut() {
+            *p = center + scale * (*p - offset);
+        }
+    }
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } => bounds,
+            BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A mesh of triangles, with a bounding volume hierarchy so that rays only test
+/// the triangles they might actually hit
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    colors: Option<Vec<Color>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    triangles: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    /// Build a mesh from loaded data. Vertex colors, if there are any, are
+    /// interpolated across each triangle and passed to the material in the hit
+    /// record, as are texture coordinates. Meshes without texture coordinates
+    /// use the barycentric coordinates of each triangle instead.
+    pub fn from(data: MeshData, material: Arc<dyn Material>) -> Self {
+        let mut triangles = data.triangles;
+        let bvh = Self::build(&data.positions, &mut triangles, 0);
+
+        Self {
+            positions: data.positions,
+            colors: data.colors,
+            uvs: data.uvs,
+            triangles,
+            material,
+            bvh,
+        }
+    }
+
+    /// Recursively build the BVH over the triangles starting at `start`,
+    /// reordering them so that each leaf covers a contiguous range
+    fn build(positions: &[Point3], triangles: &mut [[usize; 3]], start: usize) -> BvhNode {
+        let tri_bounds = |tri: &[usize; 3]| {
+            let mut b = Aabb::empty();
+            for i in tri.iter() {
+                b.add_point(positions[*i]);
+            }
+            b
+        };
+
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for tri in triangles.iter() {
+            let b = tri_bounds(tri);
+            bounds = bounds.surrounding(&b);
+            centroid_bounds.add_point(b.centroid());
+        }
+
+        if triangles.len() <= MAX_LEAF_TRIANGLES {
+            return BvhNode::Leaf {
+                bounds,
+                start,
+                end: start + triangles.len(),
+            };
+        }
+
+        // Split at the median along the axis the centroids are most spread out
+        let axis = centroid_bounds.longest_axis();
+        triangles.sort_by(|a, b| {
+            tri_bounds(a).centroid()[axis].total_cmp(&tri_bounds(b).centroid()[axis])
+        });
+
+        let mid = triangles.len() / 2;
+        let (left, right) = triangles.split_at_mut(mid);
+
+        BvhNode::Interior {
+            bounds,
+            left: Box::new(Self::build(positions, left, start)),
+            right: Box::new(Self::build(positions, right, start + mid)),
+        }
+    }
+
+    /// Intersect a single triangle using the Möller–Trumbore algorithm, which
+    /// gives the distance along the ray and the barycentric coordinates of the
+    /// hit point
+    fn hit_triangle(
+        &self,
+        index: usize,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64, f64)> {
+        let [i0, i1, i2] = self.triangles[index];
+        let p0 = self.positions[i0];
+        let edge1 = self.positions[i1] - p0;
+        let edge2 = self.positions[i2] - p0;
+
+        let pvec = ray.direction().cross(edge2);
+        let det = edge1.dot(pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin() - p0;
+        let u = tvec.dot(pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let v = ray.direction().dot(qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_det;
+        if t < t_max && t > t_min {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    fn hit_node(
+        &self,
+        node: &BvhNode,
+        ray: &Ray,
+        t_min: f64,
+        closest: &mut f64,
+        hit: &mut Option<(usize, f64, f64)>,
+        cost: &mut usize,
+    ) {
+        *cost += 1;
+        if !node.bounds().hit(ray, t_min, *closest) {
+            return;
+        }
+
+        match node {
+            BvhNode::Leaf { start, end, .. } => {
+                for index in *start..*end {
+                    *cost += 1;
+                    if let Some((t, u, v)) = self.hit_triangle(index, ray, t_min, *closest) {
+                        *closest = t;
+                        *hit = Some((index, u, v));
+                    }
+                }
+            }
+            BvhNode::Interior { left, right, .. } => {
+                self.hit_node(left, ray, t_min, closest, hit, cost);
+                self.hit_node(right, ray, t_min, closest, hit, cost);
+            }
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let mut closest = t_max;
+        let mut hit = None;
+
+        self.hit_node(&self.bvh, ray, t_min, &mut closest, &mut hit, &mut 0);
+
+        let (index, u, v) = match hit {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let [i0, i1, i2] = self.triangles[index];
+        let p0 = self.positions[i0];
+        let outward_normal: Vec3 = (self.positions[i1] - p0)
+            .cross(self.positions[i2] - p0)
+            .unit_vector();
+
+        hit_record.t = closest;
+        hit_record.p = ray.at(closest);
+        hit_record.set_face_normal(ray, outward_normal);
+        hit_record.material = Some(self.material.clone());
+        hit_record.color = match &self.colors {
+            Some(colors) => (1.0 - u - v) * colors[i0] + u * colors[i1] + v * colors[i2],
+            None => Color::from(1.0, 1.0, 1.0),
+        };
+
+        let edge1 = self.positions[i1] - p0;
+        let edge2 = self.positions[i2] - p0;
+        match &self.uvs {
+            Some(uvs) => {
+                let (uv0, uv1, uv2) = (uvs[i0], uvs[i1], uvs[i2]);
+                let lerp = |a: f64, b: f64, c: f64| (1.0 - u - v) * a + u * b + v * c;
+
+                // Solve for how the position changes with the texture
+                // coordinates across the triangle
+                let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+                let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+                let det = du1 * dv2 - dv1 * du2;
+                let (dpdu, dpdv) = if det.abs() < 1e-12 {
+                    (Vec3::new(), Vec3::new())
+                } else {
+                    (
+                        (dv2 * edge1 - dv1 * edge2) / det,
+                        (du1 * edge2 - du2 * edge1) / det,
+                    )
+                };
+
+                hit_record.set_uv(
+                    lerp(uv0.0, uv1.0, uv2.0),
+                    lerp(uv0.1, uv1.1, uv2.1),
+                    dpdu,
+                    dpdv,
+                );
+            }
+            None => hit_record.set_uv(u, v, edge1, edge2),
+        }
+        hit_record.barycentrics = Some(Vec3::from(1.0 - u - v, u, v));
+
+        true
+    }
+
+    fn traversal_cost(&self, ray: &Ray, t_min: f64, t_max: f64) -> usize {
+        let mut closest = t_max;
+        let mut cost = 0;
+        self.hit_node(&self.bvh, ray, t_min, &mut closest, &mut None, &mut cost);
+
+        cost
+    }
+}
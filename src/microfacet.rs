@@ -0,0 +1,260 @@
+use std::f64::consts::PI;
+
+use crate::{Color, Vec3};
+
+/// Below this roughness a surface is treated as perfectly smooth, since the
+/// distribution becomes too sharp to evaluate reliably
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// The GGX / Trowbridge-Reitz distribution of microfacet normals.
+///
+/// All directions are in the local shading frame, with the macro surface
+/// normal along +Z.
+///
+/// https://www.pbr-book.org/4ed/Reflection_Models/Roughness_Using_Microfacet_Theory
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn from(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    /// An isotropic distribution for the given perceptual roughness in [0, 1],
+    /// which is squared to get a more even change in appearance
+    pub fn from_roughness(roughness: f64) -> Self {
+        let alpha = roughness * roughness;
+        Self::from(alpha, alpha)
+    }
+
+    /// The perceptual roughness, going by the wider of the two directions
+    pub fn roughness(&self) -> f64 {
+        self.alpha_x.max(self.alpha_y).sqrt()
+    }
+
+    /// The same distribution made at least as rough as `roughness`, for
+    /// blurring sharp reflections that are hard to find light through
+    pub fn regularized(&self, roughness: f64) -> Self {
+        let alpha = roughness * roughness;
+        Self::from(self.alpha_x.max(alpha), self.alpha_y.max(alpha))
+    }
+
+    /// Whether the surface is smooth enough to be treated as a perfect mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacets with normal `wm`
+    pub fn d(&self, wm: Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let denom = x * x + y * y + wm.z() * wm.z();
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * denom * denom)
+    }
+
+    /// Smith's auxiliary function, measuring how much of the surface seen from
+    /// `w` is hidden by other microfacets
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let alpha2_tan2 =
+            ((self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2)) / (w.z() * w.z());
+
+        ((1.0 + alpha2_tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of microfacets visible from `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the microfacet normals that are visible from `w`
+    pub fn visible_d(&self, w: Vec3, wm: Vec3) -> f64 {
+        self.g1(w) / w.z().abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    /// The BSDF for reflecting off microfacets with normal `wm`, without the
+    /// Fresnel term, times the cosine of `wi`
+    pub fn reflection(&self, wo: Vec3, wi: Vec3, wm: Vec3) -> f64 {
+        self.d(wm) * self.g(wo, wi) / (4.0 * wo.z().abs())
+    }
+
+    /// Density of getting `wi` by reflecting `wo` off a sampled visible normal
+    pub fn reflection_pdf(&self, wo: Vec3, wm: Vec3) -> f64 {
+        self.visible_d(wo, wm) / (4.0 * wo.dot(wm).abs())
+    }
+
+    /// The BSDF for refracting through microfacets with normal `wm`, without
+    /// the Fresnel term, times the cosine of `wi`
+    pub fn transmission(&self, wo: Vec3, wi: Vec3, wm: Vec3, eta: f64) -> f64 {
+        let denom = (wi.dot(wm) * eta + wo.dot(wm)).powi(2);
+
+        self.d(wm) * self.g(wo, wi) * eta * eta * (wi.dot(wm) * wo.dot(wm)).abs()
+            / (wo.z().abs() * denom)
+    }
+
+    /// Density of getting `wi` by refracting `wo` through a sampled visible
+    /// normal, which differs from reflection in how the directions change
+    /// with the normal
+    pub fn transmission_pdf(&self, wo: Vec3, wi: Vec3, wm: Vec3, eta: f64) -> f64 {
+        let denom = (wi.dot(wm) * eta + wo.dot(wm)).powi(2);
+
+        self.visible_d(wo, wm) * eta * eta * wi.dot(wm).abs() / denom
+    }
+
+    /// Sample a microfacet normal visible from `w`, with density `visible_d`,
+    /// given two uniform random numbers.
+    ///
+    /// "Sampling the GGX Distribution of Visible Normals", Heitz 2018
+    pub fn sample_wm(&self, w: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere
+        let mut wh = Vec3::from(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let len_sq = wh.x() * wh.x() + wh.y() * wh.y();
+        let t1 = if len_sq > 0.0 {
+            Vec3::from(-wh.y(), wh.x(), 0.0) / len_sq.sqrt()
+        } else {
+            Vec3::from(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+
+        // Sample the projected area of the visible hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+
+        // And unstretch it again
+        Vec3::from(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit_vector()
+    }
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction
+/// `eta + ik`, for a single wavelength
+fn fresnel_conductor_channel(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Fresnel reflectance of a conductor, for each color channel
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+
+    Color::from(
+        fresnel_conductor_channel(cos_i, eta.x(), k.x()),
+        fresnel_conductor_channel(cos_i, eta.y(), k.y()),
+        fresnel_conductor_channel(cos_i, eta.z(), k.z()),
+    )
+}
+
+/// The microfacet normal that turns `wo` into `wi` by reflection or refraction,
+/// on the same side as the macro surface normal, with `eta` as in
+/// `fresnel_dielectric`. Returns `None` for pairs of directions no microfacet
+/// can connect.
+pub fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    let reflect = wo.z() * wi.z() > 0.0;
+    let wm = if reflect { wo + wi } else { wi * eta + wo };
+    if wm.length_squared() == 0.0 {
+        return None;
+    }
+
+    let wm = wm.unit_vector();
+    let wm = if wm.z() < 0.0 { -wm } else { wm };
+
+    // Refraction has to go through the microfacet, not bounce off it
+    if !reflect && wm.dot(wi) * wm.dot(wo) > 0.0 {
+        return None;
+    }
+
+    Some(wm)
+}
+
+/// Reflect `w` about the normal `n`, both pointing away from the surface
+pub fn reflect(w: Vec3, n: Vec3) -> Vec3 {
+    -w + 2.0 * w.dot(n) * n
+}
+
+/// Exact Fresnel reflectance of an interface between two dielectrics, where
+/// `eta` is the index of refraction below the surface over the one above it.
+/// A negative `cos_i` means the light arrives from below.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i.max(-1.0), 1.0 / eta)
+    } else {
+        (cos_i.min(1.0), eta)
+    };
+
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+/// Refract `w` through the normal `n`, both pointing away from the surface,
+/// with `eta` as in `fresnel_dielectric`. Returns `None` on total internal
+/// reflection.
+pub fn refract(w: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = w.dot(n);
+    let (cos_i, eta, n) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta, -n)
+    } else {
+        (cos_i, eta, n)
+    };
+
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    Some(-w / eta + (cos_i / eta - cos_t) * n)
+}
@@ -0,0 +1,54 @@
+use crate::Vec3;
+
+/// An orthonormal basis, used to move directions into and out of the local
+/// shading frame of a surface, where the normal points along +Z
+///
+/// https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#orthonormalbases
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Build a basis around `n`, with the other two axes picked arbitrarily
+    pub fn from_w(n: Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::from(0.0, 1.0, 0.0)
+        } else {
+            Vec3::from(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).unit_vector();
+        let u = w.cross(v);
+
+        Self { u, v, w }
+    }
+
+    /// Build a basis around `n` with its first axis along `tangent`, made
+    /// perpendicular to `n`, so anything anisotropic lines up with the
+    /// surface. Falls back to an arbitrary basis without a tangent to go by.
+    pub fn from_w_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = tangent - tangent.dot(w) * w;
+        if u.length_squared() < 1e-16 {
+            return Self::from_w(n);
+        }
+
+        let u = u.unit_vector();
+        let v = w.cross(u);
+
+        Self { u, v, w }
+    }
+
+    /// Turn a direction in the local frame into world space
+    pub fn local_to_world(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    /// Turn a world space direction into the local frame
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::from(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,481 @@
+use std::io;
+
+use crate::mesh::MeshData;
+use crate::{Color, Point3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::Int8,
+            "uchar" | "uint8" => Scalar::Uint8,
+            "short" | "int16" => Scalar::Int16,
+            "ushort" | "uint16" => Scalar::Uint16,
+            "int" | "int32" => Scalar::Int32,
+            "uint" | "uint32" => Scalar::Uint32,
+            "float" | "float32" => Scalar::Float32,
+            "double" | "float64" => Scalar::Float64,
+            _ => return Err(invalid_data(format!("unknown property type: {}", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::Uint8 => 1,
+            Scalar::Int16 | Scalar::Uint16 => 2,
+            Scalar::Int32 | Scalar::Uint32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    /// The value that means "full intensity" for a color channel of this type
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::Int8 => i8::MAX as f64,
+            Scalar::Uint8 => u8::MAX as f64,
+            Scalar::Int16 => i16::MAX as f64,
+            Scalar::Uint16 => u16::MAX as f64,
+            Scalar::Int32 => i32::MAX as f64,
+            Scalar::Uint32 => u32::MAX as f64,
+            Scalar::Float32 | Scalar::Float64 => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar {
+        name: String,
+        ty: Scalar,
+    },
+    List {
+        name: String,
+        count: Scalar,
+        item: Scalar,
+    },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } => name,
+            Property::List { name, .. } => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The data following the header, read one value at a time
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Scalar) -> io::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(unexpected_eof)?;
+                token
+                    .parse()
+                    .map_err(|_| invalid_data(format!("bad value: {}", token)))
+            }
+            Body::Binary {
+                bytes,
+                pos,
+                big_endian,
+            } => {
+                let size = ty.size();
+                if *pos + size > bytes.len() {
+                    return Err(unexpected_eof());
+                }
+
+                // Copy the value out in little endian order, whatever the file uses
+                let mut raw = [0u8; 8];
+                raw[..size].copy_from_slice(&bytes[*pos..*pos + size]);
+                if *big_endian {
+                    raw[..size].reverse();
+                }
+                *pos += size;
+
+                let mut b2 = [0u8; 2];
+                let mut b4 = [0u8; 4];
+                b2.copy_from_slice(&raw[..2]);
+                b4.copy_from_slice(&raw[..4]);
+
+                Ok(match ty {
+                    Scalar::Int8 => raw[0] as i8 as f64,
+                    Scalar::Uint8 => raw[0] as f64,
+                    Scalar::Int16 => i16::from_le_bytes(b2) as f64,
+                    Scalar::Uint16 => u16::from_le_bytes(b2) as f64,
+                    Scalar::Int32 => i32::from_le_bytes(b4) as f64,
+                    Scalar::Uint32 => u32::from_le_bytes(b4) as f64,
+                    Scalar::Float32 => f32::from_le_bytes(b4) as f64,
+                    Scalar::Float64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+
+    /// Read every property of one element into `values`, with each list
+    /// flattened into its length followed by its items
+    fn read_element(&mut self, element: &Element, values: &mut Vec<f64>) -> io::Result<()> {
+        values.clear();
+
+        for property in element.properties.iter() {
+            match property {
+                Property::Scalar { ty, .. } => values.push(self.read(*ty)?),
+                Property::List { count, item, .. } => {
+                    let n = self.read(*count)? as usize;
+                    values.push(n as f64);
+                    for _ in 0..n {
+                        values.push(self.read(*item)?);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "PLY file ended early")
+}
+
+/// Parse the header, returning the format, the elements it declares, and where
+/// the body starts
+fn parse_header(bytes: &[u8]) -> io::Result<(Format, Vec<Element>, usize)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut pos = 0;
+
+    loop {
+        let end = bytes[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| pos + i)
+            .ok_or_else(|| invalid_data("missing end_header".to_string()))?;
+
+        let line = String::from_utf8_lossy(&bytes[pos..end]);
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        pos = end + 1;
+
+        match words.as_slice() {
+            ["ply"] | [] => {}
+            ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid_data(format!("unknown format: {}", name))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data(format!("bad element count: {}", count)))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property before element".to_string()))?;
+                element.properties.push(Property::List {
+                    name: name.to_string(),
+                    count: Scalar::parse(count)?,
+                    item: Scalar::parse(item)?,
+                });
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property before element".to_string()))?;
+                element.properties.push(Property::Scalar {
+                    name: name.to_string(),
+                    ty: Scalar::parse(ty)?,
+                });
+            }
+            ["end_header"] => break,
+            _ => return Err(invalid_data(format!("bad header line: {}", line))),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing format".to_string()))?;
+
+    Ok((format, elements, pos))
+}
+
+/// Parse a PLY (Stanford polygon) file, as produced by most 3D scanners, in any
+/// of its three formats. Polygons are split into triangle fans, and vertex
+/// colors are read from the `red`, `green` and `blue` properties if present.
+/// Texture coordinates go by a few names, and are read from any of them.
+///
+/// http://paulbourke.net/dataformats/ply/
+pub fn parse(bytes: &[u8]) -> io::Result<MeshData> {
+    if !bytes.starts_with(b"ply") {
+        return Err(invalid_data("not a PLY file".to_string()));
+    }
+
+    let (format, elements, body_start) = parse_header(bytes)?;
+
+    let text;
+    let mut body = match format {
+        Format::Ascii => {
+            text = String::from_utf8_lossy(&bytes[body_start..]);
+            Body::Ascii(text.split_ascii_whitespace())
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Body::Binary {
+            bytes,
+            pos: body_start,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut positions = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut triangles = vec![];
+    let mut values = vec![];
+
+    for element in elements.iter() {
+        // Where each property's values start, since lists take up more than one
+        let mut offsets = vec![];
+        let find = |name: &str| -> Option<usize> {
+            element.properties.iter().position(|p| p.name() == name)
+        };
+
+        match element.name.as_str() {
+            "vertex" => {
+                let xyz = [find("x"), find("y"), find("z")];
+                let rgb = [find("red"), find("green"), find("blue")];
+                let uv = [("u", "v"), ("s", "t"), ("texture_u", "texture_v")]
+                    .iter()
+                    .find_map(|(u, v)| Some((find(u)?, find(v)?)));
+
+                if xyz.iter().any(|i| i.is_none()) {
+                    return Err(invalid_data("vertices are missing a position".to_string()));
+                }
+                let has_color = rgb.iter().all(|i| i.is_some());
+
+                for _ in 0..element.count {
+                    body.read_element(element, &mut values)?;
+                    property_offsets(element, &values, &mut offsets);
+
+                    let get = |i: Option<usize>| values[offsets[i.unwrap()]];
+                    positions.push(Point3::from(get(xyz[0]), get(xyz[1]), get(xyz[2])));
+
+                    if has_color {
+                        let channel = |i: Option<usize>| {
+                            let scale = match &element.properties[i.unwrap()] {
+                                Property::Scalar { ty, .. } => ty.color_scale(),
+                                Property::List { .. } => 1.0,
+                            };
+
+                            // Colors are stored gamma encoded, so undo the same
+                            // gamma of 2 we apply when writing the image.
+                            // Negative signed values are treated as black.
+                            (get(i) / scale).max(0.0).powi(2)
+                        };
+                        colors.push(Color::from(
+                            channel(rgb[0]),
+                            channel(rgb[1]),
+                            channel(rgb[2]),
+                        ));
+                    }
+
+                    if let Some((u, v)) = uv {
+                        uvs.push((values[offsets[u]], values[offsets[v]]));
+                    }
+                }
+            }
+            "face" => {
+                let indices = find("vertex_indices")
+                    .or_else(|| find("vertex_index"))
+                    .ok_or_else(|| invalid_data("faces are missing vertex indices".to_string()))?;
+
+                for _ in 0..element.count {
+                    body.read_element(element, &mut values)?;
+                    property_offsets(element, &values, &mut offsets);
+
+                    let start = offsets[indices];
+                    let n = values[start] as usize;
+                    let corner = |i: usize| values[start + 1 + i] as usize;
+
+                    for i in 1..n.saturating_sub(1) {
+                        triangles.push([corner(0), corner(i), corner(i + 1)]);
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    body.read_element(element, &mut values)?;
+                }
+            }
+        }
+    }
+
+    if triangles.iter().flatten().any(|i| *i >= positions.len()) {
+        return Err(invalid_data("face refers to a missing vertex".to_string()));
+    }
+
+    Ok(MeshData {
+        positions,
+        colors: if colors.is_empty() {
+            None
+        } else {
+            Some(colors)
+        },
+        uvs: if uvs.is_empty() { None } else { Some(uvs) },
+        triangles,
+    })
+}
+
+/// Work out where each property of an element starts in its flattened values
+fn property_offsets(element: &Element, values: &[f64], offsets: &mut Vec<usize>) {
+    offsets.clear();
+
+    let mut pos = 0;
+    for property in element.properties.iter() {
+        offsets.push(pos);
+        pos += match property {
+            Property::Scalar { .. } => 1,
+            Property::List { .. } => 1 + values[pos] as usize,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii() {
+        let text = "ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+4 0 1 2 3
+";
+
+        let mesh = parse(text.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors.unwrap()[2], Color::from(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let mut bytes = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+
+        for value in [0.0f32, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 3.0, 0.0].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.push(3);
+        for index in [0u32, 1, 2].iter() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let mesh = parse(&bytes).unwrap();
+        assert_eq!(mesh.positions[1], Point3::from(2.0, 0.0, 0.0));
+        assert_eq!(mesh.positions[2], Point3::from(0.0, 3.0, 0.0));
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert!(mesh.colors.is_none());
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let mut bytes = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property double x
+property double y
+property double z
+property char red
+property short green
+property int blue
+element face 1
+property list uchar int vertex_indices
+end_header
+"
+        .to_vec();
+
+        let vertices = [(0.0f64, 0.0, 0.0), (2.0, 0.0, 0.0), (0.0, 3.0, 0.0)];
+        for &(x, y, z) in vertices.iter() {
+            for value in [x, y, z].iter() {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+            bytes.extend_from_slice(&i8::MAX.to_be_bytes());
+            bytes.extend_from_slice(&(i16::MAX / 2).to_be_bytes());
+            bytes.extend_from_slice(&(-1i32).to_be_bytes());
+        }
+        bytes.push(3);
+        for index in [0i32, 1, 2].iter() {
+            bytes.extend_from_slice(&index.to_be_bytes());
+        }
+
+        let mesh = parse(&bytes).unwrap();
+        assert_eq!(mesh.positions[1], Point3::from(2.0, 0.0, 0.0));
+        assert_eq!(mesh.positions[2], Point3::from(0.0, 3.0, 0.0));
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+
+        let color = mesh.colors.unwrap()[0];
+        assert_eq!(color.x(), 1.0);
+        assert!((color.y() - 0.25).abs() < 1e-4);
+        assert_eq!(color.z(), 0.0);
+    }
+}
@@ -0,0 +1,396 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::{self, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::sampler;
+use crate::{Color, HitRecord, Ray, Vec3};
+
+/// Roughness is kept above this so every lobe stays a proper microfacet
+/// distribution that can be evaluated and mixed with the others
+const MIN_ROUGHNESS: f64 = 0.04;
+/// Reflectance of the clear coat at normal incidence, an index of refraction
+/// of 1.5
+const CLEARCOAT_F0: f64 = 0.04;
+
+/// The lobes a direction can be sampled from
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const TRANSMISSION: usize = 2;
+const CLEARCOAT: usize = 3;
+
+/// Parameters of the principled material, all between 0 and 1. `new()` gives
+/// plain grey plastic, so only the interesting ones need changing.
+#[derive(Debug, Clone, Copy)]
+pub struct PrincipledParams {
+    pub base_color: Color,
+    /// Blends from a dielectric to a metal tinted by the base color
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Strength of the highlight on dielectrics, with 0.5 being an index of
+    /// refraction of 1.5. This also sets how strongly transmission refracts.
+    pub specular: f64,
+    /// Stretches highlights along the surface
+    pub anisotropic: f64,
+    /// A soft rim at grazing angles, like on cloth
+    pub sheen: f64,
+    /// How much the sheen takes on the base color instead of white
+    pub sheen_tint: f64,
+    /// Strength of a clear varnish layer on top of everything else
+    pub clearcoat: f64,
+    /// How smooth the clear coat is
+    pub clearcoat_gloss: f64,
+    /// Blends from an opaque diffuse base to glass tinted by the base color
+    pub transmission: f64,
+}
+
+impl PrincipledParams {
+    pub fn new() -> Self {
+        Self {
+            base_color: Color::from(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            anisotropic: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+        }
+    }
+}
+
+/// One material covering most real surfaces, after Disney's principled BSDF.
+///
+/// The lobes are layered so energy is conserved: the clear coat takes its
+/// share of the light first, and what's left is split between metal and
+/// dielectric. The dielectric reflects by its Fresnel term, and the rest of
+/// the light is either diffused or transmitted.
+///
+/// "Physically Based Shading at Disney", Burley 2012
+pub struct Principled {
+    params: PrincipledParams,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    ref_idx: f64,
+}
+
+impl Principled {
+    pub fn from(params: PrincipledParams) -> Self {
+        let alpha = params.roughness.max(MIN_ROUGHNESS).powi(2);
+        let aspect = (1.0 - 0.9 * params.anisotropic).sqrt();
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * params.clearcoat_gloss;
+
+        // Disney maps the specular parameter to a reflectance of up to 8% at
+        // normal incidence, which pins down the index of refraction
+        let f0_sqrt = (0.08 * params.specular).clamp(1e-4, 0.99).sqrt();
+
+        Self {
+            params,
+            distribution: TrowbridgeReitz::from(alpha / aspect, alpha * aspect),
+            clearcoat_distribution: TrowbridgeReitz::from(clearcoat_alpha, clearcoat_alpha),
+            ref_idx: (1.0 + f0_sqrt) / (1.0 - f0_sqrt),
+        }
+    }
+
+    /// The same material with its specular lobes made at least as rough as
+    /// `roughness`
+    fn regularized(&self, roughness: f64) -> Self {
+        Self {
+            params: self.params,
+            distribution: self.distribution.regularized(roughness),
+            clearcoat_distribution: self.clearcoat_distribution.regularized(roughness),
+            ref_idx: self.ref_idx,
+        }
+    }
+
+    /// Index of refraction on the far side of the surface over the one on the
+    /// side the ray came from
+    fn relative_eta(&self, front_face: bool) -> f64 {
+        if front_face {
+            self.ref_idx
+        } else {
+            1.0 / self.ref_idx
+        }
+    }
+
+    /// How much light the clear coat reflects, leaving the rest for the layers
+    /// below
+    fn clearcoat_weight(&self, cos: f64) -> f64 {
+        self.params.clearcoat * schlick(CLEARCOAT_F0, cos)
+    }
+
+    /// Probabilities of sampling each lobe, roughly following how much light
+    /// they reflect. Rays inside the object only ever meet the glass.
+    fn lobe_probs(&self, wo: Vec3, front_face: bool) -> [f64; 4] {
+        if !front_face {
+            return [0.0, 0.0, 1.0, 0.0];
+        }
+
+        let p = &self.params;
+        let coat = self.clearcoat_weight(wo.z());
+        let base = 1.0 - coat;
+        let dielectric = 1.0 - p.metallic;
+        let fresnel = microfacet::fresnel_dielectric(wo.z(), self.ref_idx);
+
+        let weights = [
+            base * dielectric * (1.0 - p.transmission) * (1.0 - fresnel),
+            base * (p.metallic + dielectric * (1.0 - p.transmission) * fresnel),
+            base * dielectric * p.transmission,
+            coat,
+        ];
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+
+        weights.map(|w| w / total)
+    }
+
+    /// Burley's diffuse with retroreflection at grazing angles, plus sheen
+    fn diffuse(&self, wo: Vec3, wi: Vec3, wm: Vec3) -> Color {
+        let p = &self.params;
+        let cos_d = wi.dot(wm);
+
+        let fd90 = 0.5 + 2.0 * p.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * (1.0 - wi.z()).powi(5))
+            * (1.0 + (fd90 - 1.0) * (1.0 - wo.z()).powi(5));
+
+        let tint = tint(p.base_color);
+        let sheen_color = Color::from(1.0, 1.0, 1.0) * (1.0 - p.sheen_tint) + tint * p.sheen_tint;
+        let sheen = p.sheen * (1.0 - cos_d).powi(5);
+
+        (retro / PI * p.base_color + sheen * sheen_color) * wi.z()
+    }
+
+    /// The BSDF times the cosine of `wi`, both in the local shading frame
+    fn eval_local(&self, wo: Vec3, wi: Vec3, front_face: bool) -> Color {
+        let p = &self.params;
+        let eta = self.relative_eta(front_face);
+
+        let wm = match microfacet::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return Color::new(),
+        };
+        let fresnel = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+
+        if !front_face {
+            let value = if wi.z() > 0.0 {
+                self.distribution.reflection(wo, wi, wm) * fresnel
+            } else {
+                self.distribution.transmission(wo, wi, wm, eta) * (1.0 - fresnel)
+            };
+
+            return Color::from(value, value, value);
+        }
+
+        let base = 1.0 - self.clearcoat_weight(wo.z());
+        let dielectric = 1.0 - p.metallic;
+
+        if wi.z() < 0.0 {
+            return base
+                * dielectric
+                * p.transmission
+                * self.distribution.transmission(wo, wi, wm, eta)
+                * (1.0 - fresnel)
+                * p.base_color;
+        }
+
+        let specular_fresnel = p.metallic * schlick_color(p.base_color, wo.dot(wm))
+            + Color::from(
+                dielectric * fresnel,
+                dielectric * fresnel,
+                dielectric * fresnel,
+            );
+        let specular = self.distribution.reflection(wo, wi, wm) * specular_fresnel;
+
+        // Only light that isn't reflected by the dielectric gets diffused
+        let diffuse_weight = dielectric
+            * (1.0 - p.transmission)
+            * (1.0 - microfacet::fresnel_dielectric(wo.z(), eta));
+        let diffuse = diffuse_weight * self.diffuse(wo, wi, wm);
+
+        let clearcoat =
+            self.clearcoat_weight(wo.dot(wm)) * self.clearcoat_distribution.reflection(wo, wi, wm);
+
+        base * (diffuse + specular) + Color::from(clearcoat, clearcoat, clearcoat)
+    }
+
+    /// Density of sampling `wi`, combining the densities of every lobe that
+    /// could have produced it
+    fn pdf_local(&self, wo: Vec3, wi: Vec3, front_face: bool) -> f64 {
+        let eta = self.relative_eta(front_face);
+        let probs = self.lobe_probs(wo, front_face);
+
+        let wm = match microfacet::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        // The transmission lobe picks between reflection and refraction by the
+        // Fresnel term of each microfacet, just like rough glass
+        let reflect_prob = microfacet::fresnel_dielectric(wo.dot(wm), eta);
+        if wi.z() < 0.0 {
+            return probs[TRANSMISSION]
+                * (1.0 - reflect_prob)
+                * self.distribution.transmission_pdf(wo, wi, wm, eta);
+        }
+
+        probs[DIFFUSE] * wi.z() / PI
+            + (probs[SPECULAR] + probs[TRANSMISSION] * reflect_prob)
+                * self.distribution.reflection_pdf(wo, wm)
+            + probs[CLEARCOAT] * self.clearcoat_distribution.reflection_pdf(wo, wm)
+    }
+
+    /// Pick a lobe and sample a direction from it
+    fn sample_local(&self, wo: Vec3, front_face: bool) -> Option<Vec3> {
+        let mut rng = sampler::rng();
+
+        let probs = self.lobe_probs(wo, front_face);
+        let mut u = rng.gen::<f64>();
+        let lobe = probs
+            .iter()
+            .position(|&p| {
+                u -= p;
+                u < 0.0
+            })
+            .unwrap_or(CLEARCOAT);
+        if probs[lobe] <= 0.0 {
+            return None;
+        }
+
+        let wi = match lobe {
+            DIFFUSE => (Vec3::from(0.0, 0.0, 1.0) + Vec3::random_unit_vector()).unit_vector(),
+            SPECULAR | CLEARCOAT => {
+                let distribution = if lobe == SPECULAR {
+                    &self.distribution
+                } else {
+                    &self.clearcoat_distribution
+                };
+                let wm = distribution.sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+
+                microfacet::reflect(wo, wm)
+            }
+            _ => {
+                let eta = self.relative_eta(front_face);
+                let wm = self
+                    .distribution
+                    .sample_wm(wo, rng.gen::<f64>(), rng.gen::<f64>());
+
+                if rng.gen::<f64>() < microfacet::fresnel_dielectric(wo.dot(wm), eta) {
+                    microfacet::reflect(wo, wm)
+                } else {
+                    match microfacet::refract(wo, wm, eta) {
+                        Some(wi) if wi.z() < 0.0 => return Some(wi),
+                        _ => return None,
+                    }
+                }
+            }
+        };
+
+        if wi.z() > 0.0 {
+            Some(wi)
+        } else {
+            None
+        }
+    }
+}
+
+/// The local shading frame, lined up with the surface's tangent so that
+/// anisotropic highlights run along it
+fn shading_frame(rec: &HitRecord) -> Onb {
+    Onb::from_w_tangent(rec.normal, rec.tangent)
+}
+
+/// Directions towards where the ray came from and where it scattered to, in
+/// the local shading frame
+fn local_dirs(ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> (Vec3, Vec3) {
+    let frame = shading_frame(rec);
+
+    (
+        frame.world_to_local(-ray_in.direction().unit_vector()),
+        frame.world_to_local(scattered.direction().unit_vector()),
+    )
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let frame = shading_frame(rec);
+        let wo = frame.world_to_local(-ray_in.direction().unit_vector());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let lobes = self.regularized(rec.min_roughness);
+        let wi = match lobes.sample_local(wo, rec.front_face) {
+            Some(wi) => wi,
+            None => return false,
+        };
+
+        // Any lobe could have produced the direction, so the weight uses the
+        // combined density of all of them
+        let pdf = lobes.pdf_local(wo, wi, rec.front_face);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        srec.scattered = Ray::from(rec.p, frame.local_to_world(wi));
+        srec.attenuation = lobes.eval_local(wo, wi, rec.front_face) / pdf;
+        srec.specular = false;
+
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if wo.z() <= 0.0 {
+            return Color::new();
+        }
+
+        self.regularized(rec.min_roughness)
+            .eval_local(wo, wi, rec.front_face)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_dirs(ray_in, rec, scattered);
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        self.regularized(rec.min_roughness)
+            .pdf_local(wo, wi, rec.front_face)
+    }
+
+    fn roughness(&self) -> f64 {
+        self.distribution.roughness()
+    }
+}
+
+/// Schlick's approximation of the Fresnel term, from the reflectance at normal
+/// incidence
+fn schlick(f0: f64, cos: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick_color(f0: Color, cos: f64) -> Color {
+    Color::from(
+        schlick(f0.x(), cos),
+        schlick(f0.y(), cos),
+        schlick(f0.z(), cos),
+    )
+}
+
+/// The hue of a color with its brightness taken out
+fn tint(color: Color) -> Color {
+    let luminance = 0.3 * color.x() + 0.6 * color.y() + 0.1 * color.z();
+
+    if luminance > 0.0 {
+        color / luminance
+    } else {
+        Color::from(1.0, 1.0, 1.0)
+    }
+}
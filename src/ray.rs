@@ -1,9 +1,13 @@
 use crate::{Point3, Vec3};
 
 //TODO; make these public and remvoe the getters
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    /// The wavelengths in nanometers carried by a ray in spectral mode, one
+    /// for each color channel. RGB rays have none.
+    wavelengths: Option<Vec3>,
 }
 
 impl Ray {
@@ -11,11 +15,26 @@ impl Ray {
         Ray {
             origin: Point3::new(),
             direction: Vec3::new(),
+            wavelengths: None,
         }
     }
 
     pub fn from(origin: Point3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelengths: None,
+        }
+    }
+
+    /// The same ray, carrying the given wavelengths
+    pub fn with_wavelengths(mut self, wavelengths: Vec3) -> Self {
+        self.wavelengths = Some(wavelengths);
+        self
+    }
+
+    pub fn wavelengths(&self) -> Option<Vec3> {
+        self.wavelengths
     }
 
     pub fn origin(&self) -> Point3 {
@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::f64::consts::PI;
+
+use rand::rngs::{StdRng, ThreadRng};
+use rand::{Error, Rng, RngCore, SeedableRng};
+
+thread_local! {
+    static PRIMARY: RefCell<Option<PrimarySamples>> = const { RefCell::new(None) };
+}
+
+/// The random numbers rendering runs on. Everything that picks directions,
+/// points on lights and the like gets them from here rather than the
+/// thread's generator directly, so a path can be traced again from the same
+/// numbers, or from ones nudged a little.
+pub fn rng() -> SampleRng {
+    SampleRng {
+        thread: rand::thread_rng(),
+    }
+}
+
+/// Run `f` with its random numbers taken from `samples` in order, handing
+/// them back along with what it returned
+pub fn replay<R, F>(samples: PrimarySamples, f: F) -> (R, PrimarySamples)
+where
+    F: FnOnce() -> R,
+{
+    PRIMARY.with(|primary| *primary.borrow_mut() = Some(samples));
+    let result = f();
+    let samples = PRIMARY
+        .with(|primary| primary.borrow_mut().take())
+        .expect("Primary samples taken while replaying");
+
+    (result, samples)
+}
+
+/// A random number generator that gives out primary samples while `replay`
+/// has some, and the thread's generator otherwise
+pub struct SampleRng {
+    thread: ThreadRng,
+}
+
+impl RngCore for SampleRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let sample = PRIMARY.with(|primary| primary.borrow_mut().as_mut().map(|p| p.next()));
+
+        // Floats are made from the top 53 bits, so put the sample there to
+        // get the same number back out
+        match sample {
+            Some(sample) => ((sample * (1u64 << 53) as f64) as u64) << 11,
+            None => self.thread.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// The numbers in [0, 1) a path was traced from, and new ones proposed in
+/// their place. A large step proposes all new numbers, and a small step
+/// nudges each of the old ones by a normally distributed amount.
+///
+/// Paths can use more numbers than the one before, so new ones are drawn as
+/// they're needed. Numbers left over from before are dropped when a proposal
+/// is accepted, since the path they were traced from didn't depend on them.
+pub struct PrimarySamples {
+    current: Vec<f64>,
+    proposed: Vec<f64>,
+    large_step: bool,
+    /// Standard deviation of the small step nudges
+    sigma: f64,
+    rng: StdRng,
+}
+
+impl PrimarySamples {
+    /// Samples with new numbers drawn from a generator seeded with `seed`,
+    /// so the same seed gives the same numbers for a large step
+    pub fn from(seed: u64, sigma: f64) -> Self {
+        Self {
+            current: Vec::new(),
+            proposed: Vec::new(),
+            large_step: true,
+            sigma,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Start proposing new numbers, with a large step or a small one
+    pub fn start(&mut self, large_step: bool) {
+        self.large_step = large_step;
+        self.proposed.clear();
+    }
+
+    /// Keep the proposed numbers in place of the current ones
+    pub fn accept(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.proposed);
+    }
+
+    fn next(&mut self) -> f64 {
+        let index = self.proposed.len();
+
+        let value = if self.large_step || index >= self.current.len() {
+            self.rng.gen::<f64>()
+        } else {
+            let nudged = self.current[index] + self.sigma * self.normal();
+            nudged - nudged.floor()
+        };
+
+        self.proposed.push(value);
+        value
+    }
+
+    /// A standard normally distributed number, by the Box-Muller transform
+    fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.rng.gen::<f64>();
+        let u2 = self.rng.gen::<f64>();
+
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
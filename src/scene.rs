@@ -0,0 +1,32 @@
+use crate::environment::{Environment, Gradient};
+use crate::light::Light;
+use crate::{HittableList, Point3};
+
+/// Everything being rendered: the objects, the lights that are sampled
+/// directly from every point they light, and the environment all around
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Vec<Box<dyn Light>>,
+    pub environment: Box<dyn Environment>,
+    /// A sphere around the part of the scene that matters, which light from
+    /// far away is aimed at when following it out from the lights
+    pub center: Point3,
+    pub radius: f64,
+}
+
+impl Scene {
+    /// A scene with no lights under the sky gradient
+    pub fn from(world: HittableList) -> Self {
+        Self {
+            world,
+            lights: vec![],
+            environment: Box::new(Gradient),
+            center: Point3::new(),
+            radius: 16.0,
+        }
+    }
+
+    pub fn push_light(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light);
+    }
+}
@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use crate::util;
+use crate::{Color, HitRecord, Hittable, Material, Point3, Ray, Vec3};
+
+/// Distance below which a marching ray counts as touching the surface
+const SURFACE_EPSILON: f64 = 1e-5;
+/// Give up on rays that haven't converged after this many steps
+const MAX_STEPS: u32 = 512;
+/// Give up on rays that have marched this far without hitting anything
+const MAX_DISTANCE: f64 = 100.0;
+
+/// A signed distance field: negative inside the surface, positive outside, and
+/// never more than the distance to the nearest point on the surface.
+///
+/// Any `Fn(Point3) -> f64` closure is a distance field, so custom shapes don't
+/// need their own type.
+pub trait Sdf {
+    fn distance(&self, p: Point3) -> f64;
+}
+
+impl<F> Sdf for F
+where
+    F: Fn(Point3) -> f64,
+{
+    fn distance(&self, p: Point3) -> f64 {
+        self(p)
+    }
+}
+
+/// Shapes defined by a distance field, rendered by sphere tracing: since the
+/// field never overestimates the distance to the surface, a ray can always step
+/// forward by the distance at its current position without passing through it.
+pub struct SdfObject {
+    sdf: Box<dyn Sdf>,
+    material: Arc<dyn Material>,
+    step_scale: f64,
+}
+
+impl SdfObject {
+    /// `step_scale` is the fraction of the field's distance that each step
+    /// advances by. Use 1.0 for exact fields, and less for fields that can
+    /// overestimate the distance, like the Mandelbulb or displaced surfaces.
+    pub fn from(sdf: Box<dyn Sdf>, material: Arc<dyn Material>, step_scale: f64) -> Self {
+        Self {
+            sdf,
+            material,
+            step_scale,
+        }
+    }
+
+    /// The gradient of the field estimated by finite differences, sampled at
+    /// the corners of a tetrahedron so that only four evaluations are needed
+    fn normal(&self, p: Point3) -> Vec3 {
+        let h = 1e-4;
+        let k1 = Vec3::from(1.0, -1.0, -1.0);
+        let k2 = Vec3::from(-1.0, -1.0, 1.0);
+        let k3 = Vec3::from(-1.0, 1.0, -1.0);
+        let k4 = Vec3::from(1.0, 1.0, 1.0);
+
+        (k1 * self.sdf.distance(p + h * k1)
+            + k2 * self.sdf.distance(p + h * k2)
+            + k3 * self.sdf.distance(p + h * k3)
+            + k4 * self.sdf.distance(p + h * k4))
+        .unit_vector()
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let ray_length = ray.direction().length();
+        let t_limit = t_max.min(MAX_DISTANCE / ray_length);
+
+        let mut t = t_min;
+
+        for _ in 0..MAX_STEPS {
+            if t >= t_limit {
+                return false;
+            }
+
+            // Rays that start inside the surface (e.g. refracted ones) march on
+            // the magnitude of the distance just the same
+            let distance = self.sdf.distance(ray.at(t)).abs();
+
+            if distance < SURFACE_EPSILON {
+                hit_record.t = t;
+                hit_record.p = ray.at(t);
+                hit_record.set_face_normal(ray, self.normal(hit_record.p));
+                hit_record.material = Some(self.material.clone());
+                hit_record.color = Color::from(1.0, 1.0, 1.0);
+                hit_record.set_uv(0.0, 0.0, Vec3::new(), Vec3::new());
+
+                return true;
+            }
+
+            t += self.step_scale * distance / ray_length;
+        }
+
+        false
+    }
+}
+
+pub struct SphereSdf {
+    center: Point3,
+    radius: f64,
+}
+
+impl SphereSdf {
+    pub fn from(center: Point3, radius: f64) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl Sdf for SphereSdf {
+    fn distance(&self, p: Point3) -> f64 {
+        (p - self.center).length() - self.radius
+    }
+}
+
+/// An axis aligned box, given by its center and the half lengths of its sides
+pub struct BoxSdf {
+    center: Point3,
+    half_extents: Vec3,
+}
+
+impl BoxSdf {
+    pub fn from(center: Point3, half_extents: Vec3) -> Self {
+        Self {
+            center,
+            half_extents,
+        }
+    }
+}
+
+impl Sdf for BoxSdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let local = p - self.center;
+        let q = Vec3::from(
+            local.x().abs() - self.half_extents.x(),
+            local.y().abs() - self.half_extents.y(),
+            local.z().abs() - self.half_extents.z(),
+        );
+
+        let outside = Vec3::from(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+
+        outside + inside
+    }
+}
+
+/// A torus lying flat in the XZ plane
+pub struct TorusSdf {
+    center: Point3,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl TorusSdf {
+    pub fn from(center: Point3, major_radius: f64, minor_radius: f64) -> Self {
+        Self {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for TorusSdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let local = p - self.center;
+        let ring = (local.x() * local.x() + local.z() * local.z()).sqrt() - self.major_radius;
+
+        (ring * ring + local.y() * local.y()).sqrt() - self.minor_radius
+    }
+}
+
+/// A line segment between `a` and `b` with rounded ends
+pub struct CapsuleSdf {
+    a: Point3,
+    b: Point3,
+    radius: f64,
+}
+
+impl CapsuleSdf {
+    pub fn from(a: Point3, b: Point3, radius: f64) -> Self {
+        Self { a, b, radius }
+    }
+}
+
+impl Sdf for CapsuleSdf {
+    fn distance(&self, p: Point3) -> f64 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = util::clamp(pa.dot(ba) / ba.length_squared(), 0.0, 1.0);
+
+        (pa - h * ba).length() - self.radius
+    }
+}
+
+/// The union of two fields, blended together where they are within `k` of
+/// each other
+pub struct SmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothUnion {
+    pub fn from(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f64) -> Self {
+        Self { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Point3) -> f64 {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        let h = util::clamp(0.5 + 0.5 * (d2 - d1) / self.k, 0.0, 1.0);
+
+        mix(d2, d1, h) - self.k * h * (1.0 - h)
+    }
+}
+
+/// `base` with `cut` carved out of it, with the edges of the cut rounded off
+/// over a distance of `k`
+pub struct SmoothSubtract {
+    base: Box<dyn Sdf>,
+    cut: Box<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothSubtract {
+    pub fn from(base: Box<dyn Sdf>, cut: Box<dyn Sdf>, k: f64) -> Self {
+        Self { base, cut, k }
+    }
+}
+
+impl Sdf for SmoothSubtract {
+    fn distance(&self, p: Point3) -> f64 {
+        let d1 = self.base.distance(p);
+        let d2 = self.cut.distance(p);
+        let h = util::clamp(0.5 - 0.5 * (d1 + d2) / self.k, 0.0, 1.0);
+
+        mix(d1, -d2, h) + self.k * h * (1.0 - h)
+    }
+}
+
+/// Repeats a field forever by folding space into a single cell around the
+/// origin. A period of zero on an axis disables repetition along it.
+///
+/// The shape should fit inside one cell, or the distances become inexact.
+pub struct Repeat {
+    inner: Box<dyn Sdf>,
+    period: Vec3,
+}
+
+impl Repeat {
+    pub fn from(inner: Box<dyn Sdf>, period: Vec3) -> Self {
+        Self { inner, period }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: Point3) -> f64 {
+        let fold = |x: f64, period: f64| {
+            if period > 0.0 {
+                x - period * (x / period).round()
+            } else {
+                x
+            }
+        };
+
+        self.inner.distance(Vec3::from(
+            fold(p.x(), self.period.x()),
+            fold(p.y(), self.period.y()),
+            fold(p.z(), self.period.z()),
+        ))
+    }
+}
+
+/// The Mandelbulb fractal, using the distance estimator from the running
+/// derivative of the iteration. The estimate is not exact, so render it with a
+/// step scale below 1.
+pub struct Mandelbulb {
+    center: Point3,
+    scale: f64,
+    power: f64,
+    iterations: u32,
+}
+
+impl Mandelbulb {
+    pub fn from(center: Point3, scale: f64, power: f64, iterations: u32) -> Self {
+        Self {
+            center,
+            scale,
+            power,
+            iterations,
+        }
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: Point3) -> f64 {
+        // Work in the fractal's own space, with its axis pointing up
+        let local = (p - self.center) / self.scale;
+        let c = Vec3::from(local.x(), local.z(), local.y());
+
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+
+            let theta = (z.z() / r).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            let zr = r.powf(self.power);
+            z =
+                zr * Vec3::from(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ) + c;
+            r = z.length();
+        }
+
+        self.scale * 0.5 * r.ln() * r / dr
+    }
+}
+
+fn mix(a: f64, b: f64, h: f64) -> f64 {
+    a * (1.0 - h) + b * h
+}
@@ -0,0 +1,193 @@
+use std::f64::consts::PI;
+
+use crate::environment::Environment;
+use crate::light::DirectionalLight;
+use crate::spectrum::xyz_to_linear_srgb;
+use crate::{Color, Vec3};
+
+/// Brings the model's luminance, in thousands of candela per square meter,
+/// down to the range the rest of the renderer works in
+const SKY_SCALE: f64 = 0.04;
+
+/// Sunlight above the atmosphere, in the same units as the sky after scaling
+const SUN_IRRADIANCE: f64 = 4.0;
+
+/// Angular radius of the sun's disk, in degrees
+const SUN_ANGULAR_RADIUS: f64 = 0.27;
+
+/// Wavelengths in micrometers standing in for red, green and blue when
+/// working out how much sunlight the atmosphere lets through
+const RGB_WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+/// Coefficients of the Perez sky distribution function for one quantity
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// Coefficients that change linearly with the turbidity, given as
+    /// (slope, intercept) pairs
+    fn from(t: f64, coeffs: [(f64, f64); 5]) -> Self {
+        let f = |i: usize| coeffs[i].0 * t + coeffs[i].1;
+
+        Self {
+            a: f(0),
+            b: f(1),
+            c: f(2),
+            d: f(3),
+            e: f(4),
+        }
+    }
+
+    /// Relative brightness at an angle `theta` from the zenith and `gamma`
+    /// from the sun
+    fn f(&self, theta: f64, gamma: f64) -> f64 {
+        (1.0 + self.a * (self.b / theta.cos()).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * gamma.cos().powi(2))
+    }
+}
+
+/// A clear daytime sky lit by the sun, from an analytic fit to simulations
+/// of the atmosphere. Turbidity goes from 2 for very clear air to around 10
+/// for haze.
+///
+/// The sun itself isn't part of the sky; `sun` gives a light to go with it.
+///
+/// "A Practical Analytic Model for Daylight", Preetham et al. 1999
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    /// Luminance and chromaticity at the zenith
+    zenith: Vec3,
+    perez: [Perez; 3],
+    /// The Perez function at the zenith, which the rest are relative to
+    zenith_f: [f64; 3],
+}
+
+impl PhysicalSky {
+    /// A sky with the sun `elevation` degrees above the horizon, and
+    /// `azimuth` degrees around from +X towards +Z
+    pub fn from(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let t = turbidity;
+        let elevation = elevation.to_radians();
+        let azimuth = azimuth.to_radians();
+        let sun_direction = Vec3::from(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        // Angle of the sun from the zenith, kept just above the horizon since
+        // the fit falls apart below it
+        let theta_s = (PI / 2.0 - elevation).min(PI / 2.0 - 0.01);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (t1, t2, t3) = (theta_s, theta_s * theta_s, theta_s.powi(3));
+        let zenith_x = t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+            + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+            + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886);
+        let zenith_yc = t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+            + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+            + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688);
+
+        let perez = [
+            Perez::from(
+                t,
+                [
+                    (0.1787, -1.4630),
+                    (-0.3554, 0.4275),
+                    (-0.0227, 5.3251),
+                    (0.1206, -2.5771),
+                    (-0.0670, 0.3703),
+                ],
+            ),
+            Perez::from(
+                t,
+                [
+                    (-0.0193, -0.2592),
+                    (-0.0665, 0.0008),
+                    (-0.0004, 0.2125),
+                    (-0.0641, -0.8989),
+                    (-0.0033, 0.0452),
+                ],
+            ),
+            Perez::from(
+                t,
+                [
+                    (-0.0167, -0.2608),
+                    (-0.0950, 0.0092),
+                    (-0.0079, 0.2102),
+                    (-0.0441, -1.6537),
+                    (-0.0109, 0.0529),
+                ],
+            ),
+        ];
+        let zenith_f = [
+            perez[0].f(0.0, theta_s),
+            perez[1].f(0.0, theta_s),
+            perez[2].f(0.0, theta_s),
+        ];
+
+        Self {
+            sun_direction,
+            turbidity,
+            zenith: Vec3::from(zenith_y, zenith_x, zenith_yc),
+            perez,
+            zenith_f,
+        }
+    }
+
+    /// The sun that lights the sky, dimmed and reddened by the air it passes
+    /// through on the way down
+    ///
+    /// Appendix A.2 of the paper, with ozone and water vapour left out
+    pub fn sun(&self) -> DirectionalLight {
+        let theta_s = self.sun_direction.y().clamp(0.0, 1.0).acos();
+
+        // How much air the light goes through compared to straight down,
+        // with a correction for the curve of the atmosphere near the horizon
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |lambda: f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+
+        DirectionalLight::from(
+            self.sun_direction,
+            SUN_IRRADIANCE
+                * Color::from(
+                    transmittance(RGB_WAVELENGTHS[0]),
+                    transmittance(RGB_WAVELENGTHS[1]),
+                    transmittance(RGB_WAVELENGTHS[2]),
+                ),
+            SUN_ANGULAR_RADIUS,
+        )
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let d = direction.unit_vector();
+
+        // Below the horizon looks the same as just above it
+        let theta = d.y().max(0.01).acos();
+        let gamma = d.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let value = |i: usize| self.zenith[i] * self.perez[i].f(theta, gamma) / self.zenith_f[i];
+        let (luminance, x, y) = (value(0), value(1), value(2));
+
+        let xyz = Vec3::from(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = SKY_SCALE * xyz_to_linear_srgb(xyz);
+
+        Color::from(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+}
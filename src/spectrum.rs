@@ -0,0 +1,222 @@
+use std::sync::OnceLock;
+
+use crate::{Color, Ray, Vec3};
+
+/// The range of visible wavelengths sampled in spectral mode, in nanometers
+const LAMBDA_MIN: f64 = 380.0;
+const LAMBDA_MAX: f64 = 780.0;
+
+/// Width of the smooth steps between the blue, green and red basis spectra
+const BASIS_STEP: f64 = 30.0;
+
+/// How a material's index of refraction changes with wavelength, which is
+/// what splits white light into a rainbow
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// n = a + b / λ², with λ in micrometers
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢλ² / (λ² - cᵢ), with λ in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, the most common optical glass
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    /// Schott SF11, a dense flint glass that disperses light strongly
+    pub fn dense_flint() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+            c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+        }
+    }
+
+    /// Index of refraction at a wavelength in nanometers
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0).powi(2);
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    /// Index of refraction at the yellow helium d line, which glasses are
+    /// usually quoted at and which stands in for all of them in RGB mode
+    pub fn d_line_ior(&self) -> f64 {
+        self.ior(587.6)
+    }
+}
+
+/// Pick the wavelengths a spectral path carries from a uniform random number:
+/// a hero wavelength, and two more spread evenly across the visible range
+/// from it, so every path covers the whole spectrum.
+///
+/// "Hero Wavelength Spectral Sampling", Wilkie et al. 2014
+pub fn sample_wavelengths(u: f64) -> Vec3 {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let rotate = |i: f64| LAMBDA_MIN + (u * range + i * range / 3.0) % range;
+
+    Vec3::from(rotate(0.0), rotate(1.0), rotate(2.0))
+}
+
+/// The CIE 1931 color matching functions at a wavelength, using the
+/// multi-lobe fit from "Simple Analytic Approximations to the CIE XYZ Color
+/// Matching Functions", Wyman et al. 2013
+fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+
+    Vec3::from(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    Color::from(
+        3.240_454_2 * xyz.x() - 1.537_138_5 * xyz.y() - 0.498_531_4 * xyz.z(),
+        -0.969_266 * xyz.x() + 1.876_010_8 * xyz.y() + 0.041_556 * xyz.z(),
+        0.055_643_4 * xyz.x() - 0.204_025_9 * xyz.y() + 1.057_225_2 * xyz.z(),
+    )
+}
+
+/// Three smooth spectra covering blue, green and red that add up to one at
+/// every wavelength, which RGB colors are built from
+fn basis(lambda: f64) -> Vec3 {
+    let step = |edge: f64| {
+        let t = ((lambda - edge) / BASIS_STEP + 0.5).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+
+    let red = step(590.0);
+    let blue = 1.0 - step(495.0);
+
+    Vec3::from(red, 1.0 - red - blue, blue)
+}
+
+/// Everything needed to go between RGB and spectra, worked out once by
+/// integrating over the visible range
+struct Tables {
+    /// Integral of the Y color matching function, so a spectrum of one has a
+    /// luminance of one
+    y_integral: f64,
+    /// Linear sRGB of a spectrum of one, which is divided out so it comes out
+    /// white rather than the slightly pink of equal energy light
+    white: Color,
+    /// Turns an RGB color into the weights of the basis spectra
+    from_rgb: [Vec3; 3],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+
+    TABLES.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let integrate = |f: &dyn Fn(f64) -> Vec3| {
+            let mut sum = Vec3::new();
+            for i in 0..steps {
+                sum += f(LAMBDA_MIN + i as f64 + 0.5);
+            }
+            sum
+        };
+
+        let y_integral = integrate(&|l| cie_xyz(l)).y();
+        let white = xyz_to_linear_srgb(integrate(&|l| cie_xyz(l)) / y_integral);
+
+        // The white balanced RGB of each basis spectrum makes up a matrix that
+        // takes basis weights to RGB, so its inverse goes the other way
+        let columns: Vec<Color> = (0..3)
+            .map(|i| {
+                let xyz = integrate(&|l| basis(l)[i] * cie_xyz(l)) / y_integral;
+                xyz_to_linear_srgb(xyz) / white
+            })
+            .collect();
+
+        Tables {
+            y_integral,
+            white,
+            from_rgb: invert([
+                Vec3::from(columns[0].x(), columns[1].x(), columns[2].x()),
+                Vec3::from(columns[0].y(), columns[1].y(), columns[2].y()),
+                Vec3::from(columns[0].z(), columns[1].z(), columns[2].z()),
+            ]),
+        }
+    })
+}
+
+/// Invert a 3x3 matrix given as rows
+fn invert(m: [Vec3; 3]) -> [Vec3; 3] {
+    // The columns of the inverse are the cross products of the rows, over
+    // the determinant
+    let c0 = m[1].cross(m[2]);
+    let c1 = m[2].cross(m[0]);
+    let c2 = m[0].cross(m[1]);
+    let det = m[0].dot(c0);
+
+    [
+        Vec3::from(c0.x(), c1.x(), c2.x()) / det,
+        Vec3::from(c0.y(), c1.y(), c2.y()) / det,
+        Vec3::from(c0.z(), c1.z(), c2.z()) / det,
+    ]
+}
+
+/// The value at each of the given wavelengths of a smooth spectrum with the
+/// given RGB color. White comes out as one everywhere.
+pub fn upsample(rgb: Color, wavelengths: Vec3) -> Color {
+    let from_rgb = &tables().from_rgb;
+    let weights = Vec3::from(
+        from_rgb[0].dot(rgb),
+        from_rgb[1].dot(rgb),
+        from_rgb[2].dot(rgb),
+    );
+
+    // Very saturated colors need more than the basis can give, so they're
+    // clipped to stay physical
+    let value = |lambda: f64| weights.dot(basis(lambda)).max(0.0);
+
+    Color::from(
+        value(wavelengths.x()),
+        value(wavelengths.y()),
+        value(wavelengths.z()),
+    )
+}
+
+/// An RGB color as carried by the ray: upsampled to its wavelengths in
+/// spectral mode, and left alone otherwise
+pub fn for_ray(rgb: Color, ray: &Ray) -> Color {
+    match ray.wavelengths() {
+        Some(wavelengths) => upsample(rgb, wavelengths),
+        None => rgb,
+    }
+}
+
+/// Turn the values a path found at its wavelengths into linear sRGB, as an
+/// estimate of the whole spectrum
+pub fn to_rgb(values: Color, wavelengths: Vec3) -> Color {
+    let tables = tables();
+
+    // Each wavelength was picked uniformly, so each is weighted by the width
+    // of the range, and the three of them are averaged
+    let weight = (LAMBDA_MAX - LAMBDA_MIN) / (3.0 * tables.y_integral);
+    let xyz = weight
+        * (values.x() * cie_xyz(wavelengths.x())
+            + values.y() * cie_xyz(wavelengths.y())
+            + values.z() * cie_xyz(wavelengths.z()));
+
+    let rgb = xyz_to_linear_srgb(xyz) / tables.white;
+
+    // Spectral colors lie outside of sRGB and can't be shown
+    Color::from(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
@@ -0,0 +1,180 @@
+use std::io;
+
+use crate::mesh::MeshData;
+use crate::Point3;
+
+/// Size of the header at the start of a binary STL file
+const HEADER_SIZE: usize = 80;
+/// Size of each triangle in a binary STL file: a normal, three vertices and a
+/// two byte attribute
+const TRIANGLE_SIZE: usize = 50;
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parse an STL file, as exported by CAD programs, in either its ASCII or
+/// binary form. Every triangle gets its own vertices, and the stored normals
+/// are ignored in favor of the winding order.
+///
+/// Binary files are allowed to start with `solid` just like ASCII ones, so
+/// they're recognized by being big enough for the triangle count instead.
+/// Some exporters pad the end, so anything past the last triangle is ignored.
+pub fn parse(bytes: &[u8]) -> io::Result<MeshData> {
+    if bytes.len() >= HEADER_SIZE + 4 {
+        let count = read_u32(bytes, HEADER_SIZE) as usize;
+
+        if bytes.len() >= HEADER_SIZE + 4 + count * TRIANGLE_SIZE {
+            return Ok(parse_binary(bytes, count));
+        }
+    }
+
+    if bytes.starts_with(b"solid") {
+        parse_ascii(&String::from_utf8_lossy(bytes))
+    } else {
+        Err(invalid_data("not an STL file".to_string()))
+    }
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[pos..pos + 4]);
+    u32::from_le_bytes(word)
+}
+
+fn read_f32(bytes: &[u8], pos: usize) -> f64 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[pos..pos + 4]);
+    f32::from_le_bytes(word) as f64
+}
+
+fn parse_binary(bytes: &[u8], count: usize) -> MeshData {
+    let mut positions = Vec::with_capacity(count * 3);
+
+    for i in 0..count {
+        // Skip the normal at the start of each triangle
+        let start = HEADER_SIZE + 4 + i * TRIANGLE_SIZE + 12;
+
+        for corner in 0..3 {
+            let pos = start + corner * 12;
+            positions.push(Point3::from(
+                read_f32(bytes, pos),
+                read_f32(bytes, pos + 4),
+                read_f32(bytes, pos + 8),
+            ));
+        }
+    }
+
+    MeshData {
+        positions,
+        colors: None,
+        uvs: None,
+        triangles: (0..count).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect(),
+    }
+}
+
+/// Only the `vertex` lines matter; each three in a row make a triangle
+fn parse_ascii(text: &str) -> io::Result<MeshData> {
+    let mut positions = vec![];
+    let mut tokens = text.split_whitespace();
+
+    while let Some(token) = tokens.next() {
+        if token != "vertex" {
+            continue;
+        }
+
+        let mut coords = [0.0; 3];
+        for coord in coords.iter_mut() {
+            let value = tokens
+                .next()
+                .ok_or_else(|| invalid_data("vertex is missing coordinates".to_string()))?;
+            *coord = value
+                .parse()
+                .map_err(|_| invalid_data(format!("bad coordinate: {}", value)))?;
+        }
+
+        positions.push(Point3::from(coords[0], coords[1], coords[2]));
+    }
+
+    if positions.is_empty() {
+        return Err(invalid_data("no facets in STL file".to_string()));
+    }
+    if positions.len() % 3 != 0 {
+        return Err(invalid_data("incomplete triangle".to_string()));
+    }
+
+    let count = positions.len() / 3;
+
+    Ok(MeshData {
+        positions,
+        colors: None,
+        uvs: None,
+        triangles: (0..count).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary STL with the given header and triangles, padded at the end
+    fn binary(header: &[u8], triangles: &[[f32; 9]], padding: usize) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_SIZE, b' ');
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+
+        for triangle in triangles {
+            bytes.extend_from_slice(&[0; 12]);
+            for value in triangle {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 2]);
+        }
+
+        bytes.resize(bytes.len() + padding, 0);
+        bytes
+    }
+
+    const TRIANGLE: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+    #[test]
+    fn ascii() {
+        let text = "solid test
+            facet normal 0 0 1
+              outer loop
+                vertex 0 0 0
+                vertex 1 0 0
+                vertex 0 1 0
+              endloop
+            endfacet
+            endsolid test";
+
+        let mesh = parse(text.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[1], Point3::from(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn ascii_without_facets() {
+        assert!(parse(b"solid empty\nendsolid empty\n").is_err());
+    }
+
+    #[test]
+    fn binary_file() {
+        let mesh = parse(&binary(b"exported", &[TRIANGLE, TRIANGLE], 0)).unwrap();
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(mesh.positions[5], Point3::from(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        let mesh = parse(&binary(b"solid exported", &[TRIANGLE], 0)).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn binary_with_padding() {
+        let mesh = parse(&binary(b"solid exported", &[TRIANGLE], 7)).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+    }
+}
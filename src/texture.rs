@@ -0,0 +1,102 @@
+use std::io;
+use std::path::Path;
+
+use crate::image::Image;
+use crate::perlin::Perlin;
+use crate::{Color, Point3};
+
+/// Something that varies over a surface, looked up by the texture coordinates
+/// of a hit, or by its position for solid textures
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
+
+/// An image wrapped around the surface, repeating outside of [0, 1]
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn from(image: Image) -> Self {
+        Self { image }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::from(Image::load(path)?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let width = self.image.width;
+        let height = self.image.height;
+
+        // Images are stored from the top, but v goes up, and pixel centers
+        // sit half way between the integer coordinates
+        let x = (u - u.floor()) * width as f64 - 0.5;
+        let y = (1.0 - (v - v.floor())) * height as f64 - 0.5;
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let wrap = |i: f64, n: usize| (i as i64).rem_euclid(n as i64) as usize;
+        let pixel = |dx: f64, dy: f64| {
+            self.image
+                .pixel(wrap(x0 + dx, width), wrap(y0 + dy, height))
+        };
+
+        (1.0 - fy) * ((1.0 - fx) * pixel(0.0, 0.0) + fx * pixel(1.0, 0.0))
+            + fy * ((1.0 - fx) * pixel(0.0, 1.0) + fx * pixel(1.0, 1.0))
+    }
+}
+
+/// Alternating squares of two colors, `scale` of them along each side of the
+/// texture
+pub struct Checker {
+    even: Color,
+    odd: Color,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn from(even: Color, odd: Color, scale: f64) -> Self {
+        Self { even, odd, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let parity = (u * self.scale).floor() as i64 + (v * self.scale).floor() as i64;
+
+        if parity.rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Solid grey turbulence from Perlin noise, with features `1 / scale` across
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn from(scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+        let t = self.noise.turb(self.scale * p, 7);
+
+        Color::from(t, t, t)
+    }
+}
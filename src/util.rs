@@ -1,3 +1,5 @@
+use crate::Color;
+
 #[inline]
 /// Clamp the given value between the upper and lower bounds
 pub fn clamp(val: f64, min: f64, max: f64) -> f64 {
@@ -11,3 +13,8 @@ pub fn clamp(val: f64, min: f64, max: f64) -> f64 {
         min
     }
 }
+
+/// How bright a linear RGB color looks
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
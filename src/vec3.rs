@@ -3,10 +3,11 @@ use std::ops;
 
 use rand::Rng;
 
+use crate::sampler;
 use crate::util;
 
 /// A collections of three points representing a location in 3D space.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec3 {
     e: [f64; 3],
 }
@@ -101,13 +102,13 @@ impl Vec3 {
 
     /// Generate a random Vec3 in with values between 0.0 and 1.0
     pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         Self::from(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())
     }
 
     /// Generate a random Vec3 in with values in the given range
     pub fn random_range(min: f64, max: f64) -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         Self::from(
             rng.gen_range(min, max),
             rng.gen_range(min, max),
@@ -120,7 +121,7 @@ impl Vec3 {
         // Lambertian distribution. We chose this distribution because it is more
         // uniform. We do this by choosing points on the surface of the unit sphere
         // offset along the surface normal
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         let a: f64 = rng.gen_range(0.0, 2.0 * f64::consts::PI);
         let z: f64 = rng.gen_range(-1.0, 1.0);
         let r = (1.0 - z * z).sqrt();
@@ -143,7 +144,7 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         loop {
             let p = Vec3::from(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0);
 
@@ -197,7 +198,7 @@ impl ops::AddAssign for Vec3 {
 impl ops::MulAssign<f64> for Vec3 {
     fn mul_assign(&mut self, other: f64) {
         *self = Self {
-            e: [self.x() + other, self.y() + other, self.z() + other],
+            e: [self.x() * other, self.y() * other, self.z() * other],
         }
     }
 }
@@ -256,6 +257,17 @@ impl ops::Mul<Vec3> for f64 {
     }
 }
 
+impl ops::Div for Vec3 {
+    type Output = Vec3;
+    fn div(self, other: Vec3) -> Self::Output {
+        Self::from(
+            self.x() / other.x(),
+            self.y() / other.y(),
+            self.z() / other.z(),
+        )
+    }
+}
+
 impl ops::Div<f64> for Vec3 {
     type Output = Vec3;
     fn div(self, t: f64) -> Self::Output {
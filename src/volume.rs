@@ -0,0 +1,521 @@
+use std::f64;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::material::{DiffuseLight, HenyeyGreenstein, Isotropic};
+use crate::perlin::Perlin;
+use crate::sampler;
+use crate::{Color, HitRecord, Hittable, Material, Point3, Ray, Vec3};
+
+/// A volume of constant density bounded by another hittable, e.g. smoke or fog.
+///
+/// Rays passing through the boundary travel a random distance inside before
+/// scattering, with the probability of scattering per unit length given by the
+/// density. The boundary must be convex (a sphere works, a list of spheres does
+/// not), since only the first entry and exit along the ray are considered.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: f64,
+    albedo: Color,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn from(boundary: Box<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            albedo,
+            phase_function: Arc::new(Isotropic::from(albedo)),
+        }
+    }
+
+    /// Scatter light more forwards, for `g` up to 1, or backwards, for `g`
+    /// down to -1, rather than evenly
+    pub fn with_anisotropy(mut self, g: f64) -> Self {
+        self.phase_function = Arc::new(HenyeyGreenstein::from(self.albedo, g));
+        self
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let mut rng = sampler::rng();
+
+        let (t_enter, t_exit) = match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let ray_length = ray.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+
+        // Sample the distance to the next scattering event from an exponential
+        // distribution
+        let hit_distance = self.neg_inv_density * (1.0 - rng.gen::<f64>()).ln();
+
+        if hit_distance > distance_inside {
+            return false;
+        }
+
+        hit_record.t = t_enter + hit_distance / ray_length;
+        hit_record.p = ray.at(hit_record.t);
+
+        // The normal and face are meaningless inside a volume
+        hit_record.normal = Vec3::from(1.0, 0.0, 0.0);
+        hit_record.front_face = true;
+        hit_record.material = Some(self.phase_function.clone());
+        hit_record.color = Color::from(1.0, 1.0, 1.0);
+        hit_record.set_uv(0.0, 0.0, Vec3::new(), Vec3::new());
+
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some((t_enter, t_exit)) => {
+                let distance_inside = (t_exit - t_enter) * ray.direction().length();
+                (distance_inside / self.neg_inv_density).exp()
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Find the part of the ray within `[t_min, t_max]` that lies inside the
+/// boundary, if any.
+///
+/// The boundary is intersected regardless of where the ray starts, so rays
+/// starting inside the volume work too.
+fn boundary_interval(
+    boundary: &dyn Hittable,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64)> {
+    let mut enter = HitRecord::new();
+    let mut exit = HitRecord::new();
+
+    if !boundary.hit(ray, -f64::INFINITY, f64::INFINITY, &mut enter) {
+        return None;
+    }
+
+    if !boundary.hit(ray, enter.t + 0.0001, f64::INFINITY, &mut exit) {
+        return None;
+    }
+
+    let t_enter = enter.t.max(t_min).max(0.0);
+    let t_exit = exit.t.min(t_max);
+
+    if t_enter >= t_exit {
+        return None;
+    }
+
+    Some((t_enter, t_exit))
+}
+
+/// A scalar density field for heterogeneous volumes
+pub trait Density {
+    /// Density at the given point, between 0 and `max_density()`
+    fn density(&self, p: Point3) -> f64;
+
+    /// Upper bound of the density anywhere in the field, used as the majorant
+    /// when tracking rays through the volume
+    fn max_density(&self) -> f64;
+}
+
+/// A dense grid of density samples filling an axis aligned box, interpolated
+/// trilinearly. Points outside the box have a density of zero.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f64>,
+    min: Point3,
+    max: Point3,
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    /// Create a grid from samples stored with x varying fastest, then y, then z
+    pub fn from(nx: usize, ny: usize, nz: usize, data: Vec<f64>, min: Point3, max: Point3) -> Self {
+        assert_eq!(
+            data.len(),
+            nx * ny * nz,
+            "Voxel count does not match grid size"
+        );
+
+        let max_value = data.iter().cloned().fold(0.0, f64::max);
+
+        Self {
+            nx,
+            ny,
+            nz,
+            data,
+            min,
+            max,
+            max_value,
+        }
+    }
+
+    /// Load a grid from a file and place it in the box between `min` and `max`.
+    ///
+    /// Files ending in `.raw` are binary: three little endian `u32`s giving the
+    /// grid size, followed by the voxels as little endian `f32`s. Anything else
+    /// is read as ASCII: the three sizes followed by the voxels, separated by
+    /// whitespace. In both cases x varies fastest, then y, then z.
+    pub fn load(path: &Path, min: Point3, max: Point3) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let raw = path.extension().is_some_and(|ext| ext == "raw");
+        let (dims, data) = parse_grid(&bytes, raw)?;
+
+        Ok(Self::from(dims[0], dims[1], dims[2], data, min, max))
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+}
+
+impl Density for VoxelGrid {
+    fn density(&self, p: Point3) -> f64 {
+        let size = self.max - self.min;
+        let local = p - self.min;
+
+        let dims = [self.nx, self.ny, self.nz];
+        let mut lo = [0; 3];
+        let mut hi = [0; 3];
+        let mut frac = [0.0; 3];
+
+        for axis in 0..3 {
+            let s = local[axis] / size[axis];
+            if !(0.0..=1.0).contains(&s) {
+                return 0.0;
+            }
+
+            // Voxel samples sit at the center of their cells
+            let g = (s * dims[axis] as f64 - 0.5).max(0.0);
+            lo[axis] = (g as usize).min(dims[axis] - 1);
+            hi[axis] = (lo[axis] + 1).min(dims[axis] - 1);
+            frac[axis] = (g - lo[axis] as f64).min(1.0);
+        }
+
+        let mut accum = 0.0;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+
+            let mut weight = 1.0;
+            for (axis, f) in frac.iter().enumerate() {
+                weight *= if pick(axis) { *f } else { 1.0 - *f };
+            }
+
+            let x = if pick(0) { hi[0] } else { lo[0] };
+            let y = if pick(1) { hi[1] } else { lo[1] };
+            let z = if pick(2) { hi[2] } else { lo[2] };
+
+            accum += weight * self.voxel(x, y, z);
+        }
+
+        accum
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_value
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parse a grid file and check that its size and voxels make sense
+fn parse_grid(bytes: &[u8], raw: bool) -> io::Result<([usize; 3], Vec<f64>)> {
+    let (dims, data) = if raw {
+        parse_raw_grid(bytes)?
+    } else {
+        parse_ascii_grid(&String::from_utf8_lossy(bytes))?
+    };
+
+    if dims.contains(&0) {
+        return Err(invalid_data("grid size must not be zero".to_string()));
+    }
+
+    let count = dims[0]
+        .checked_mul(dims[1])
+        .and_then(|n| n.checked_mul(dims[2]))
+        .ok_or_else(|| {
+            invalid_data(format!(
+                "grid size {}x{}x{} is too large",
+                dims[0], dims[1], dims[2]
+            ))
+        })?;
+
+    if data.len() != count {
+        return Err(invalid_data(format!(
+            "expected {} voxels but found {}",
+            count,
+            data.len()
+        )));
+    }
+
+    if let Some(i) = data.iter().position(|d| !d.is_finite() || *d < 0.0) {
+        return Err(invalid_data(format!(
+            "voxel {} has invalid density {}",
+            i, data[i]
+        )));
+    }
+
+    Ok((dims, data))
+}
+
+fn parse_ascii_grid(text: &str) -> io::Result<([usize; 3], Vec<f64>)> {
+    let mut tokens = text.split_whitespace();
+    let mut dims = [0; 3];
+
+    for dim in dims.iter_mut() {
+        *dim = tokens
+            .next()
+            .and_then(|tok| tok.parse().ok())
+            .ok_or_else(|| invalid_data("missing grid size".to_string()))?;
+    }
+
+    let data = tokens
+        .map(|tok| {
+            tok.parse::<f64>()
+                .map_err(|_| invalid_data(format!("bad voxel value: {}", tok)))
+        })
+        .collect::<io::Result<Vec<f64>>>()?;
+
+    Ok((dims, data))
+}
+
+fn parse_raw_grid(bytes: &[u8]) -> io::Result<([usize; 3], Vec<f64>)> {
+    if bytes.len() < 12 {
+        return Err(invalid_data("missing grid size".to_string()));
+    }
+
+    let mut dims = [0; 3];
+    for (i, dim) in dims.iter_mut().enumerate() {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[i * 4..i * 4 + 4]);
+        *dim = u32::from_le_bytes(word) as usize;
+    }
+
+    let data = bytes[12..]
+        .chunks_exact(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word.copy_from_slice(chunk);
+            f32::from_le_bytes(word) as f64
+        })
+        .collect();
+
+    Ok((dims, data))
+}
+
+/// Procedural density from turbulent Perlin noise, clamped to `[0, density]`
+pub struct NoiseDensity {
+    noise: Perlin,
+    density: f64,
+    frequency: f64,
+}
+
+impl NoiseDensity {
+    pub fn from(density: f64, frequency: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            density,
+            frequency,
+        }
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: Point3) -> f64 {
+        self.density * self.noise.turb(self.frequency * p, 7).min(1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.density
+    }
+}
+
+/// A volume whose density varies in space, bounded by another hittable.
+///
+/// The extinction at a point is `(absorption + scattering) * density`. Rays are
+/// tracked through the volume with delta tracking: candidate collisions are
+/// sampled against the maximum density, and accepted as real collisions with
+/// probability proportional to the actual density there. A real collision
+/// scatters the ray with probability `scattering / (absorption + scattering)`,
+/// otherwise the ray is absorbed and picks up the emitted radiance.
+pub struct HeterogeneousMedium {
+    boundary: Box<dyn Hittable>,
+    density: Box<dyn Density>,
+    absorption: f64,
+    scattering: f64,
+    phase_function: Arc<dyn Material>,
+    emission: Arc<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    pub fn from(
+        boundary: Box<dyn Hittable>,
+        density: Box<dyn Density>,
+        absorption: f64,
+        scattering: f64,
+        emission: Color,
+    ) -> Self {
+        Self {
+            boundary,
+            density,
+            absorption,
+            scattering,
+            phase_function: Arc::new(Isotropic::from(Color::from(1.0, 1.0, 1.0))),
+            emission: Arc::new(DiffuseLight::from(emission)),
+        }
+    }
+
+    fn extinction(&self, p: Point3) -> f64 {
+        (self.absorption + self.scattering) * self.density.density(p)
+    }
+
+    fn majorant(&self) -> f64 {
+        (self.absorption + self.scattering) * self.density.max_density()
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        let mut rng = sampler::rng();
+
+        let (t_enter, t_exit) = match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return false;
+        }
+
+        let ray_length = ray.direction().length();
+        let mut t = t_enter;
+
+        // Delta tracking: step between candidate collisions sampled from the
+        // majorant until one turns out to be real or the ray leaves the volume
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * ray_length);
+            if t >= t_exit {
+                return false;
+            }
+
+            let p = ray.at(t);
+            if rng.gen::<f64>() * majorant < self.extinction(p) {
+                hit_record.t = t;
+                hit_record.p = p;
+                hit_record.normal = Vec3::from(1.0, 0.0, 0.0);
+                hit_record.front_face = true;
+                hit_record.color = Color::from(1.0, 1.0, 1.0);
+                hit_record.set_uv(0.0, 0.0, Vec3::new(), Vec3::new());
+
+                let scatter_prob = self.scattering / (self.absorption + self.scattering);
+                hit_record.material = if rng.gen::<f64>() < scatter_prob {
+                    Some(self.phase_function.clone())
+                } else {
+                    Some(self.emission.clone())
+                };
+
+                return true;
+            }
+        }
+    }
+
+    /// Estimate the fraction of light that passes through the volume between
+    /// `t_min` and `t_max` using ratio tracking, which weights each candidate
+    /// collision by its probability of being a null collision instead of
+    /// stopping at the first real one. This gives a much smoother estimate than
+    /// delta tracking when only the transmittance is needed, e.g. for shadow rays.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut rng = sampler::rng();
+
+        let (t_enter, t_exit) = match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let ray_length = ray.direction().length();
+        let mut transmittance = 1.0;
+        let mut t = t_enter;
+
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * ray_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+
+            transmittance *= 1.0 - self.extinction(ray.at(t)) / majorant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary grid with the given size and voxels
+    fn raw(dims: [u32; 3], voxels: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for dim in &dims {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+        }
+        for voxel in voxels {
+            bytes.extend_from_slice(&voxel.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii() {
+        let (dims, data) = parse_grid(b"2 1 1\n0.5 1.5\n", false).unwrap();
+        assert_eq!(dims, [2, 1, 1]);
+        assert_eq!(data, vec![0.5, 1.5]);
+    }
+
+    #[test]
+    fn binary() {
+        let (dims, data) = parse_grid(&raw([1, 2, 1], &[0.25, 2.0]), true).unwrap();
+        assert_eq!(dims, [1, 2, 1]);
+        assert_eq!(data, vec![0.25, 2.0]);
+    }
+
+    #[test]
+    fn size_overflow() {
+        let max = u32::MAX;
+        let err = parse_grid(&raw([max, max, max], &[1.0]), true).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+
+        let huge = format!("{} {} 2 1.0", usize::MAX, usize::MAX);
+        let err = parse_grid(huge.as_bytes(), false).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+
+    #[test]
+    fn invalid_density() {
+        for text in &["2 1 1 1.0 -0.5", "2 1 1 NaN 1.0", "2 1 1 1.0 inf"] {
+            let err = parse_grid(text.as_bytes(), false).unwrap_err();
+            assert!(err.to_string().contains("invalid density"), "{}", text);
+        }
+
+        let err = parse_grid(&raw([1, 1, 1], &[f32::NAN]), true).unwrap_err();
+        assert!(err.to_string().contains("invalid density"));
+    }
+}